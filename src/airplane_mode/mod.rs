@@ -3,7 +3,7 @@
 
 use std::collections::HashMap;
 
-mod rfkill;
+pub mod rfkill;
 
 use futures::{FutureExt, StreamExt};
 use iced_futures::Subscription;
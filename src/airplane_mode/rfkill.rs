@@ -16,10 +16,21 @@ use tokio::io::unix::AsyncFd;
 const RFKILL_OP_ADD: u8 = 0;
 const RFKILL_OP_DEL: u8 = 1;
 const RFKILL_OP_CHANGE: u8 = 2;
+const RFKILL_OP_CHANGE_ALL: u8 = 3;
+
+pub const RFKILL_TYPE_ALL: u8 = 0;
+pub const RFKILL_TYPE_WLAN: u8 = 1;
+pub const RFKILL_TYPE_BLUETOOTH: u8 = 2;
+pub const RFKILL_TYPE_UWB: u8 = 3;
+pub const RFKILL_TYPE_WIMAX: u8 = 4;
+pub const RFKILL_TYPE_WWAN: u8 = 5;
+pub const RFKILL_TYPE_GPS: u8 = 6;
+pub const RFKILL_TYPE_FM: u8 = 7;
+pub const RFKILL_TYPE_NFC: u8 = 8;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Default)]
-pub struct rfkill_event {
+pub(crate) struct rfkill_event {
     pub idx: u32,
     pub type_: u8,
     pub op: u8,
@@ -95,6 +106,79 @@ pub fn rfkill_updates()
     }))
 }
 
+/// Reads the current state of every rfkill device.
+pub fn rfkill_state() -> io::Result<HashMap<u32, DeviceState>> {
+    let file = fs::File::options()
+        .read(true)
+        .custom_flags(rustix::fs::OFlags::NONBLOCK.bits() as _)
+        .open("/dev/rfkill")?;
+
+    // Opening the device queues an add event for each existing device.
+    let mut devices = HashMap::new();
+    let mut event = rfkill_event::default();
+    loop {
+        match read_event(&file, &mut event) {
+            Ok(()) => (),
+            Err(rustix::io::Errno::WOULDBLOCK) => break,
+            Err(err) => return Err(err.into()),
+        }
+        if event.op == RFKILL_OP_ADD {
+            devices.insert(
+                event.idx,
+                DeviceState {
+                    type_: event.type_,
+                    soft: event.soft != 0,
+                    hard: event.hard != 0,
+                },
+            );
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Sets the soft block of every device of the given type, or of all devices with
+/// `RFKILL_TYPE_ALL`.
+pub fn set_soft_block_all(type_: u8, block: bool) -> io::Result<()> {
+    write_event(&rfkill_event {
+        type_,
+        op: RFKILL_OP_CHANGE_ALL,
+        soft: block.into(),
+        ..Default::default()
+    })
+}
+
+/// Sets the soft block of the device with the given index.
+pub fn set_soft_block(idx: u32, block: bool) -> io::Result<()> {
+    write_event(&rfkill_event {
+        idx,
+        op: RFKILL_OP_CHANGE,
+        soft: block.into(),
+        ..Default::default()
+    })
+}
+
+fn write_event(event: &rfkill_event) -> io::Result<()> {
+    let dev = fs::File::options().write(true).open("/dev/rfkill")?;
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            event as *const _ as *const u8,
+            mem::size_of::<rfkill_event>(),
+        )
+    };
+    let written = rustix::io::write(&dev, bytes)?;
+    if written != bytes.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            format!(
+                "wrote {written} of {} bytes of an rfkill event",
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
 fn read_event(dev: &fs::File, event: &mut rfkill_event) -> rustix::io::Result<()> {
     let bytes = unsafe {
         slice::from_raw_parts_mut(event as *mut _ as *mut u8, mem::size_of::<rfkill_event>())
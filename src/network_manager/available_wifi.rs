@@ -2,17 +2,28 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use cosmic_dbus_networkmanager::{
-    device::wireless::WirelessDevice,
-    interface::enums::{ApFlags, ApSecurityFlags, DeviceState},
+    access_point::AccessPoint as NmAccessPoint,
+    device::{SpecificDevice, wireless::WirelessDevice},
+    interface::{
+        access_point::AccessPointProxy,
+        enums::{ApFlags, ApSecurityFlags, DeviceState},
+    },
+    nm::NetworkManager,
 };
 
-use futures::StreamExt;
+use futures::{
+    SinkExt, StreamExt,
+    future::{AbortHandle, Either},
+    stream::BoxStream,
+};
+use iced_futures::{Subscription, stream};
 use itertools::Itertools;
-use std::{collections::HashMap, sync::Arc};
-use zbus::zvariant::ObjectPath;
+use std::{collections::HashMap, fmt::Debug, hash::Hash, sync::Arc};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
 
-use super::hw_address::HwAddress;
+use super::{Event, SSID, hw_address::HwAddress};
 
+/// Scans for access points, returning one entry per BSSID sorted by strength.
 pub async fn handle_wireless_device(
     device: WirelessDevice<'_>,
     hw_address: Option<String>,
@@ -34,71 +45,313 @@ pub async fn handle_wireless_device(
 
     let access_points = device.get_access_points().await?;
 
-    let state: DeviceState = device
+    let state = device_state(&device).await;
+
+    let mut aps = Vec::with_capacity(access_points.len());
+    for ap in access_points {
+        if let Some(access_point) = access_point(&ap, state, hw_address.as_deref()).await {
+            aps.push(access_point);
+        }
+    }
+
+    aps.sort_by_key(|ap| std::cmp::Reverse(ap.strength));
+    Ok(aps)
+}
+
+/// Groups access points by SSID and device, keeping the strongest of each group.
+pub fn strongest_per_ssid(access_points: &[AccessPoint]) -> Vec<AccessPoint> {
+    let mut aps = HashMap::<(&SSID, HwAddress), &AccessPoint>::new();
+    for access_point in access_points {
+        let key = (&access_point.ssid, access_point.hw_address);
+        if let Some(existing) = aps.get(&key) {
+            if existing.strength > access_point.strength {
+                continue;
+            }
+        }
+
+        aps.insert(key, access_point);
+    }
+
+    aps.into_values()
+        .cloned()
+        .sorted_by(|a, b| b.strength.cmp(&a.strength))
+        .collect()
+}
+
+async fn device_state(device: &WirelessDevice<'_>) -> DeviceState {
+    device
         .upcast()
         .await
         .and_then(|dev| dev.cached_state())
         .unwrap_or_default()
         .map(|s| s.into())
-        .unwrap_or_else(|| DeviceState::Unknown);
+        .unwrap_or_else(|| DeviceState::Unknown)
+}
 
-    // Sort by strength and remove duplicates
-    let mut aps = HashMap::<String, AccessPoint>::new();
-    for ap in access_points {
-        let (ssid_res, strength_res) = futures::join!(ap.ssid(), ap.strength());
+/// A wireless device whose access points are being followed.
+struct WirelessSource {
+    device: WirelessDevice<'static>,
+    hw_address: Option<String>,
+}
 
-        if let Some((ssid, strength)) = ssid_res.ok().zip(strength_res.ok()) {
-            let ssid = String::from_utf8_lossy(&ssid.clone()).into_owned();
-            if let Some(access_point) = aps.get(&ssid) {
-                if access_point.strength > strength {
-                    continue;
-                }
-            }
+enum Change {
+    Added(OwnedObjectPath, Arc<WirelessSource>),
+    Removed(OwnedObjectPath),
+    Strength(ObjectPath<'static>, u8),
+}
+
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: zbus::Connection,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+/// Follows the access points of every wireless device, emitting changes as they happen.
+///
+/// Unlike [`handle_wireless_device`], access points are reported individually rather than
+/// grouped by SSID. An added access point replaces any earlier one with the same path, and is
+/// only sent again when its details change.
+pub async fn watch(conn: zbus::Connection, mut output: futures::channel::mpsc::Sender<Event>) {
+    let network_manager = match NetworkManager::new(&conn).await {
+        Ok(n) => n,
+        Err(why) => {
+            tracing::error!(why = why.to_string(), "Failed to connect to NetworkManager");
+            return;
+        }
+    };
+
+    let mut devices_changed = network_manager.receive_devices_changed().await;
+    let mut strength_watchers = HashMap::<OwnedObjectPath, AbortHandle>::new();
+    // The access points sent as added, as they were last sent.
+    let mut sent = HashMap::<OwnedObjectPath, AccessPoint>::new();
 
-            let Ok(flags) = ap.rsn_flags().await else {
+    loop {
+        let mut changes = futures::stream::SelectAll::<BoxStream<'static, Change>>::new();
+        let previous = std::mem::take(&mut strength_watchers);
+
+        for device in network_manager.devices().await.unwrap_or_default() {
+            let Ok(Some(SpecificDevice::Wireless(wireless))) = device.downcast_to_device().await
+            else {
                 continue;
             };
-            let network_type = if flags.intersects(ApSecurityFlags::KEY_MGMT_802_1X) {
-                NetworkType::EAP
-            } else if flags.intersects(ApSecurityFlags::KEY_MGMTPSK) {
-                NetworkType::PSK
-            } else if flags.is_empty() {
-                NetworkType::Open
-            } else {
+
+            let (added, removed) = futures::join!(
+                wireless.receive_access_point_added(),
+                wireless.receive_access_point_removed()
+            );
+
+            let (Ok(added), Ok(removed)) = (added, removed) else {
                 continue;
             };
 
-            aps.insert(
-                ssid.clone(),
-                AccessPoint {
-                    ssid: Arc::from(ssid),
-                    strength,
-                    state,
-                    working: false,
-                    path: ap.inner().path().to_owned(),
-                    secured: !ap.wpa_flags().await?.is_empty(),
-                    wps_push: ap.flags().await?.contains(ApFlags::WPS_PBC),
-                    network_type,
-                    hw_address: hw_address
-                        .as_ref()
-                        .and_then(|str_addr| HwAddress::from_str(str_addr))
-                        .unwrap_or_default(),
-                },
+            let source = Arc::new(WirelessSource {
+                device: wireless.clone(),
+                hw_address: device.hw_address().await.ok(),
+            });
+
+            let state = device_state(&wireless).await;
+
+            for ap in wireless.get_access_points().await.unwrap_or_default() {
+                let Some(info) = access_point(&ap, state, source.hw_address.as_deref()).await
+                else {
+                    continue;
+                };
+
+                let path = OwnedObjectPath::from(ap.inner().path().to_owned());
+                let (strength, handle) = watch_strength(&ap).await;
+                changes.push(strength);
+                strength_watchers.insert(path.clone(), handle);
+                send_added(&mut output, &mut sent, path, info).await;
+            }
+
+            changes.push(
+                added
+                    .filter_map(move |signal| {
+                        let source = source.clone();
+                        async move {
+                            let path = signal.args().ok()?.access_point().to_owned();
+                            Some(Change::Added(path.into(), source))
+                        }
+                    })
+                    .boxed(),
             );
+
+            changes.push(
+                removed
+                    .filter_map(|signal| async move {
+                        let path = signal.args().ok()?.access_point().to_owned();
+                        Some(Change::Removed(path.into()))
+                    })
+                    .boxed(),
+            );
+        }
+
+        for (_, handle) in previous {
+            handle.abort();
+        }
+
+        let gone: Vec<OwnedObjectPath> = sent
+            .keys()
+            .filter(|path| !strength_watchers.contains_key(*path))
+            .cloned()
+            .collect();
+
+        for path in gone {
+            sent.remove(&path);
+            _ = output
+                .send(Event::AccessPointRemoved(path.into_inner()))
+                .await;
+        }
+
+        loop {
+            match futures::future::select(changes.next(), devices_changed.next()).await {
+                Either::Left((Some(Change::Added(path, source)), _)) => {
+                    let Ok(proxy) = AccessPointProxy::new(&conn, path.clone()).await else {
+                        continue;
+                    };
+
+                    let ap = NmAccessPoint::from(proxy);
+                    let state = device_state(&source.device).await;
+
+                    let Some(info) = access_point(&ap, state, source.hw_address.as_deref()).await
+                    else {
+                        continue;
+                    };
+
+                    let (strength, handle) = watch_strength(&ap).await;
+                    changes.push(strength);
+                    if let Some(previous) = strength_watchers.insert(path.clone(), handle) {
+                        previous.abort();
+                    }
+
+                    send_added(&mut output, &mut sent, path, info).await;
+                }
+
+                Either::Left((Some(Change::Removed(path)), _)) => {
+                    if let Some(handle) = strength_watchers.remove(&path) {
+                        handle.abort();
+                    }
+
+                    if sent.remove(&path).is_some() {
+                        _ = output
+                            .send(Event::AccessPointRemoved(path.into_inner()))
+                            .await;
+                    }
+                }
+
+                Either::Left((Some(Change::Strength(path, strength)), _)) => {
+                    let Some(ap) = sent.get_mut(&OwnedObjectPath::from(path.clone())) else {
+                        continue;
+                    };
+
+                    ap.strength = strength;
+                    _ = output
+                        .send(Event::AccessPointStrengthChanged(path, strength))
+                        .await;
+                }
+
+                Either::Left((None, _)) => {
+                    if devices_changed.next().await.is_none() {
+                        return;
+                    }
+                    break;
+                }
+
+                Either::Right((Some(_), _)) => break,
+                Either::Right((None, _)) => return,
+            }
         }
     }
+}
 
-    let aps = aps
-        .into_values()
-        .sorted_by(|a, b| b.strength.cmp(&a.strength))
-        .collect();
+/// Sends an access point as added, unless it was already sent with the same details.
+async fn send_added(
+    output: &mut futures::channel::mpsc::Sender<Event>,
+    sent: &mut HashMap<OwnedObjectPath, AccessPoint>,
+    path: OwnedObjectPath,
+    info: AccessPoint,
+) {
+    if sent.get(&path) == Some(&info) {
+        return;
+    }
 
-    Ok(aps)
+    sent.insert(path, info.clone());
+    _ = output.send(Event::AccessPointAdded(info)).await;
+}
+
+/// Follows the signal strength of an access point until aborted.
+async fn watch_strength(ap: &NmAccessPoint<'static>) -> (BoxStream<'static, Change>, AbortHandle) {
+    let path = ap.inner().path().to_owned();
+
+    let changes = ap
+        .receive_strength_changed()
+        .await
+        .filter_map(move |change| {
+            let path = path.clone();
+            async move { Some(Change::Strength(path, change.get().await.ok()?)) }
+        });
+
+    let (changes, handle) = futures::stream::abortable(changes);
+    (changes.boxed(), handle)
+}
+
+/// Reads the properties of an access point seen by a wireless device.
+async fn access_point(
+    ap: &NmAccessPoint<'_>,
+    state: DeviceState,
+    hw_address: Option<&str>,
+) -> Option<AccessPoint> {
+    let (ssid, strength, rsn_flags, wpa_flags, flags) = futures::join!(
+        ap.ssid(),
+        ap.strength(),
+        ap.rsn_flags(),
+        ap.wpa_flags(),
+        ap.flags()
+    );
+
+    let (bssid, frequency, max_bitrate, mode, last_seen) = futures::join!(
+        ap.inner().get_property::<String>("HwAddress"),
+        ap.inner().get_property::<u32>("Frequency"),
+        ap.inner().get_property::<u32>("MaxBitrate"),
+        ap.inner().get_property::<u32>("Mode"),
+        ap.inner().get_property::<i32>("LastSeen"),
+    );
+
+    let frequency = frequency.unwrap_or_default();
+
+    Some(AccessPoint {
+        ssid: SSID::from(ssid.ok()?),
+        strength: strength.ok()?,
+        state,
+        working: false,
+        path: ap.inner().path().to_owned(),
+        secured: !wpa_flags.ok()?.is_empty(),
+        wps_push: flags.ok()?.contains(ApFlags::WPS_PBC),
+        network_type: NetworkType::from_rsn_flags(rsn_flags.ok()?)?,
+        hw_address: hw_address.and_then(HwAddress::from_str).unwrap_or_default(),
+        bssid: bssid
+            .ok()
+            .and_then(|bssid| HwAddress::from_str(&bssid))
+            .unwrap_or_default(),
+        frequency,
+        band: Band::from_frequency(frequency),
+        channel: channel_from_frequency(frequency),
+        max_bitrate: max_bitrate.unwrap_or_default(),
+        mode: ApMode::from(mode.unwrap_or_default()),
+        last_seen: last_seen.ok().and_then(|secs| u32::try_from(secs).ok()),
+    })
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AccessPoint {
-    pub ssid: Arc<str>,
+    pub ssid: SSID,
     pub strength: u8,
     pub state: DeviceState,
     pub working: bool,
@@ -107,14 +360,173 @@ pub struct AccessPoint {
     pub secured: bool,
     pub wps_push: bool,
     pub network_type: NetworkType,
+    /// Hardware address of the access point itself; `hw_address` is the device's.
+    pub bssid: HwAddress,
+    /// Frequency in MHz.
+    pub frequency: u32,
+    pub band: Option<Band>,
+    pub channel: Option<u32>,
+    /// Maximum bitrate in kbit/s.
+    pub max_bitrate: u32,
+    pub mode: ApMode,
+    /// Seconds since boot when the access point was last found in a scan.
+    pub last_seen: Option<u32>,
+}
+
+/// The 802.11 mode of an access point.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ApMode {
+    #[default]
+    Unknown,
+    Adhoc,
+    Infrastructure,
+    Ap,
+    Mesh,
+}
+
+impl From<u32> for ApMode {
+    fn from(mode: u32) -> Self {
+        match mode {
+            1 => Self::Adhoc,
+            2 => Self::Infrastructure,
+            3 => Self::Ap,
+            4 => Self::Mesh,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Wi-Fi frequency band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Band {
+    TwoPointFourGhz,
+    FiveGhz,
+    SixGhz,
 }
 
-// TODO do we want to support eap methods other than peap in the applet?
-// Then we'd need a dropdown for the eap method,
-// and tls requires a cert instead of a password
+impl Band {
+    /// The `802-11-wireless.band` value for this band, if profiles can be restricted to it.
+    pub fn setting(self) -> Option<&'static str> {
+        match self {
+            Self::TwoPointFourGhz => Some("bg"),
+            Self::FiveGhz => Some("a"),
+            Self::SixGhz => None,
+        }
+    }
+
+    /// The band of a frequency in MHz.
+    pub fn from_frequency(frequency: u32) -> Option<Self> {
+        match frequency {
+            2401..=2495 => Some(Self::TwoPointFourGhz),
+            5150..=5895 => Some(Self::FiveGhz),
+            5925..=7125 => Some(Self::SixGhz),
+            _ => None,
+        }
+    }
+
+    /// The band of a channel number, with channels above 14 being 5 GHz.
+    pub fn from_channel(channel: u32) -> Self {
+        if channel <= 14 {
+            Self::TwoPointFourGhz
+        } else {
+            Self::FiveGhz
+        }
+    }
+}
+
+/// The channel number of a frequency in MHz.
+pub fn channel_from_frequency(frequency: u32) -> Option<u32> {
+    match frequency {
+        2484 => Some(14),
+        2412..=2472 => Some((frequency - 2407) / 5),
+        5150..=5895 => Some((frequency - 5000) / 5),
+        // Channel 2 is the only 6 GHz channel off the 5 MHz grid starting at 5950.
+        5935 => Some(2),
+        5955..=7115 => Some((frequency - 5950) / 5),
+        _ => None,
+    }
+}
+
+// Key management bits of `NM80211ApSecurityFlags` added alongside WPA3.
+const KEY_MGMT_SAE: u32 = 0x400;
+const KEY_MGMT_OWE: u32 = 0x800;
+const KEY_MGMT_OWE_TM: u32 = 0x1000;
+const KEY_MGMT_EAP_SUITE_B_192: u32 = 0x2000;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NetworkType {
     Open,
     PSK,
+    /// WPA-Enterprise, configured with [`super::eap::EnterpriseCredentials`].
     EAP,
+    /// WPA3-Personal.
+    Sae,
+    /// WPA2/WPA3-Personal transition mode, accepting both PSK and SAE.
+    SaeTransition,
+    /// Enhanced Open, including OWE transition mode networks.
+    Owe,
+}
+
+impl NetworkType {
+    pub fn from_rsn_flags(flags: ApSecurityFlags) -> Option<Self> {
+        let bits = flags.bits();
+        let sae = bits & KEY_MGMT_SAE != 0;
+        let psk = flags.intersects(ApSecurityFlags::KEY_MGMTPSK);
+
+        if flags.intersects(ApSecurityFlags::KEY_MGMT_802_1X)
+            || bits & KEY_MGMT_EAP_SUITE_B_192 != 0
+        {
+            Some(Self::EAP)
+        } else if sae && psk {
+            Some(Self::SaeTransition)
+        } else if sae {
+            Some(Self::Sae)
+        } else if psk {
+            Some(Self::PSK)
+        } else if bits & (KEY_MGMT_OWE | KEY_MGMT_OWE_TM) != 0 {
+            Some(Self::Owe)
+        } else if flags.is_empty() {
+            Some(Self::Open)
+        } else {
+            None
+        }
+    }
+
+    /// The `802-11-wireless-security.key-mgmt` value for this network.
+    pub fn key_mgmt(self) -> Option<&'static str> {
+        match self {
+            Self::Open => None,
+            // NetworkManager upgrades to SAE on transition networks when the device supports it.
+            Self::PSK | Self::SaeTransition => Some("wpa-psk"),
+            Self::EAP => Some("wpa-eap"),
+            Self::Sae => Some("sae"),
+            Self::Owe => Some("owe"),
+        }
+    }
+
+    /// Whether connecting requires a password or other credentials.
+    pub fn requires_secret(self) -> bool {
+        !matches!(self, Self::Open | Self::Owe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_from_frequency() {
+        assert_eq!(channel_from_frequency(2412), Some(1));
+        assert_eq!(channel_from_frequency(2484), Some(14));
+        assert_eq!(channel_from_frequency(5180), Some(36));
+        assert_eq!(channel_from_frequency(5825), Some(165));
+        assert_eq!(channel_from_frequency(5935), Some(2));
+        assert_eq!(channel_from_frequency(5955), Some(1));
+        assert_eq!(channel_from_frequency(6115), Some(33));
+        assert_eq!(channel_from_frequency(60480), None);
+
+        assert_eq!(Band::from_frequency(2437), Some(Band::TwoPointFourGhz));
+        assert_eq!(Band::from_frequency(5500), Some(Band::FiveGhz));
+        assert_eq!(Band::from_frequency(6115), Some(Band::SixGhz));
+    }
 }
@@ -0,0 +1,115 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::Event;
+use cosmic_dbus_networkmanager::{interface::enums::NmConnectivityState, nm::NetworkManager};
+use futures::{SinkExt, StreamExt};
+use iced_futures::{Subscription, stream};
+use std::{fmt::Debug, hash::Hash};
+use zbus::Connection;
+
+#[derive(Debug, Clone)]
+pub enum State {
+    Continue(Connection),
+    Error,
+}
+
+/// Follows NetworkManager's connectivity state, emitting [`Event::Connectivity`] on each change
+/// and [`Event::CaptivePortal`] when a captive portal is detected.
+///
+/// A connectivity check is requested when the subscription starts, so that a stale state is not
+/// reported until NetworkManager's next periodic check.
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: Connection,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+pub async fn watch(conn: zbus::Connection, mut output: futures::channel::mpsc::Sender<Event>) {
+    let mut state = State::Continue(conn);
+
+    loop {
+        state = start_listening(state, &mut output).await;
+    }
+}
+
+async fn start_listening(
+    state: State,
+    output: &mut futures::channel::mpsc::Sender<Event>,
+) -> State {
+    let conn = match state {
+        State::Continue(conn) => conn,
+        State::Error => futures::future::pending().await,
+    };
+
+    let network_manager = match NetworkManager::new(&conn).await {
+        Ok(n) => n,
+        Err(why) => {
+            tracing::error!(why = why.to_string(), "Failed to connect to NetworkManager");
+            return State::Error;
+        }
+    };
+
+    let mut connectivity_changed = network_manager.receive_connectivity_changed().await;
+
+    let mut connectivity = match network_manager.check_connectivity().await {
+        Ok(connectivity) => NmConnectivityState::from(connectivity),
+        Err(why) => {
+            tracing::warn!(?why, "connectivity check failed");
+            network_manager
+                .connectivity()
+                .await
+                .unwrap_or(NmConnectivityState::Unknown)
+        }
+    };
+
+    send(&network_manager, output, connectivity).await;
+
+    while let Some(change) = connectivity_changed.next().await {
+        let Ok(next) = change.get().await.map(NmConnectivityState::from) else {
+            continue;
+        };
+
+        if next != connectivity {
+            send(&network_manager, output, next).await;
+            connectivity = next;
+        }
+    }
+
+    State::Continue(conn)
+}
+
+async fn send(
+    network_manager: &NetworkManager<'_>,
+    output: &mut futures::channel::mpsc::Sender<Event>,
+    connectivity: NmConnectivityState,
+) {
+    _ = output.send(Event::Connectivity(connectivity)).await;
+
+    if connectivity == NmConnectivityState::Portal {
+        _ = output
+            .send(Event::CaptivePortal {
+                check_uri: connectivity_check_uri(network_manager).await,
+            })
+            .await;
+    }
+}
+
+/// The URI NetworkManager probes to detect captive portals.
+///
+/// Opening it in a browser while behind a portal redirects to the portal's sign-in page.
+async fn connectivity_check_uri(network_manager: &NetworkManager<'_>) -> Option<String> {
+    network_manager
+        .inner()
+        .get_property::<String>("ConnectivityCheckUri")
+        .await
+        .ok()
+        .filter(|uri| !uri.is_empty())
+}
@@ -1,11 +1,18 @@
 // Copyright 2024 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use super::{ConnectionSettings, SSID, connection_settings, proxy::ProxyConfig, setting_value};
 use cosmic_dbus_networkmanager::{
-    active_connection::ActiveConnection, device::SpecificDevice,
-    interface::enums::ActiveConnectionState,
+    active_connection::ActiveConnection,
+    device::SpecificDevice,
+    interface::enums::{ActiveConnectionState, DeviceType},
 };
-use std::net::Ipv4Addr;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
 
 pub async fn active_connections(
     active_connections: Vec<ActiveConnection<'_>>,
@@ -19,6 +26,12 @@ pub async fn active_connections(
             .await
             .unwrap_or_default();
         let addresses: Vec<_> = ipv4.iter().map(|d| d.address).collect();
+        let ip_details = ip_details(&connection).await;
+        let settings = profile_settings(&connection).await;
+        let proxy = settings
+            .as_ref()
+            .map(ProxyConfig::from_settings)
+            .unwrap_or_default();
         let state = connection
             .state()
             .await
@@ -28,6 +41,8 @@ pub async fn active_connections(
             info.push(ActiveConnectionInfo::Vpn {
                 name: connection.id().await?,
                 ip_addresses: addresses.clone(),
+                ip_details: ip_details.clone(),
+                proxy: proxy.clone(),
             });
             continue;
         }
@@ -44,13 +59,22 @@ pub async fn active_connections(
                         hw_address: wired_device.hw_address().await?,
                         speed: wired_device.speed().await?,
                         ip_addresses: addresses.clone(),
+                        ip_details: ip_details.clone(),
+                        proxy: proxy.clone(),
                     });
                 }
                 Some(SpecificDevice::Wireless(wireless_device)) => {
                     if let Ok(access_point) = wireless_device.active_access_point().await {
+                        let ssid = match settings.as_ref().and_then(wifi_ssid) {
+                            Some(ssid) => ssid,
+                            None => SSID::from(access_point.ssid().await?),
+                        };
                         info.push(ActiveConnectionInfo::WiFi {
-                            name: String::from_utf8_lossy(&access_point.ssid().await?).into_owned(),
+                            name: ssid.to_string_lossy().into_owned(),
+                            ssid,
                             ip_addresses: addresses.clone(),
+                            ip_details: ip_details.clone(),
+                            proxy: proxy.clone(),
                             hw_address: wireless_device.hw_address().await?,
                             state,
                             strength: access_point.strength().await.unwrap_or_default(),
@@ -61,6 +85,16 @@ pub async fn active_connections(
                     info.push(ActiveConnectionInfo::Vpn {
                         name: connection.id().await?,
                         ip_addresses: addresses.clone(),
+                        ip_details: ip_details.clone(),
+                        proxy: proxy.clone(),
+                    });
+                }
+                _ if matches!(device.device_type().await, Ok(DeviceType::Modem)) => {
+                    info.push(ActiveConnectionInfo::MobileBroadband {
+                        name: connection.id().await?,
+                        ip_addresses: addresses.clone(),
+                        ip_details: ip_details.clone(),
+                        proxy: proxy.clone(),
                     });
                 }
                 _ => {}
@@ -73,6 +107,7 @@ pub async fn active_connections(
             ActiveConnectionInfo::Vpn { name, .. } => format!("0{name}"),
             ActiveConnectionInfo::Wired { name, .. } => format!("1{name}"),
             ActiveConnectionInfo::WiFi { name, .. } => format!("2{name}"),
+            ActiveConnectionInfo::MobileBroadband { name, .. } => format!("3{name}"),
         };
         helper(a).cmp(&helper(b))
     });
@@ -80,6 +115,16 @@ pub async fn active_connections(
     Ok(info)
 }
 
+/// Addressing and name resolution details beyond the IPv4 addresses.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IpDetails {
+    pub ipv6_addresses: Vec<Ipv6Addr>,
+    pub ipv4_gateway: Option<Ipv4Addr>,
+    pub ipv6_gateway: Option<Ipv6Addr>,
+    pub dns_servers: Vec<IpAddr>,
+    pub search_domains: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ActiveConnectionInfo {
     Wired {
@@ -87,10 +132,15 @@ pub enum ActiveConnectionInfo {
         hw_address: String,
         speed: u32,
         ip_addresses: Vec<Ipv4Addr>,
+        ip_details: IpDetails,
+        proxy: ProxyConfig,
     },
     WiFi {
         name: String,
+        ssid: SSID,
         ip_addresses: Vec<Ipv4Addr>,
+        ip_details: IpDetails,
+        proxy: ProxyConfig,
         hw_address: String,
         state: ActiveConnectionState,
         strength: u8,
@@ -98,6 +148,14 @@ pub enum ActiveConnectionInfo {
     Vpn {
         name: String,
         ip_addresses: Vec<Ipv4Addr>,
+        ip_details: IpDetails,
+        proxy: ProxyConfig,
+    },
+    MobileBroadband {
+        name: String,
+        ip_addresses: Vec<Ipv4Addr>,
+        ip_details: IpDetails,
+        proxy: ProxyConfig,
     },
 }
 
@@ -107,6 +165,236 @@ impl ActiveConnectionInfo {
             Self::Wired { name, .. } => name.clone(),
             Self::WiFi { name, .. } => name.clone(),
             Self::Vpn { name, .. } => name.clone(),
+            Self::MobileBroadband { name, .. } => name.clone(),
+        }
+    }
+
+    /// The SSID of a Wi-Fi connection, as raw bytes rather than its display name.
+    pub fn ssid(&self) -> Option<&SSID> {
+        match &self {
+            Self::WiFi { ssid, .. } => Some(ssid),
+            _ => None,
+        }
+    }
+
+    pub fn ip_details(&self) -> &IpDetails {
+        match &self {
+            Self::Wired { ip_details, .. } => ip_details,
+            Self::WiFi { ip_details, .. } => ip_details,
+            Self::Vpn { ip_details, .. } => ip_details,
+            Self::MobileBroadband { ip_details, .. } => ip_details,
+        }
+    }
+
+    pub fn proxy(&self) -> &ProxyConfig {
+        match &self {
+            Self::Wired { proxy, .. } => proxy,
+            Self::WiFi { proxy, .. } => proxy,
+            Self::Vpn { proxy, .. } => proxy,
+            Self::MobileBroadband { proxy, .. } => proxy,
+        }
+    }
+}
+
+/// Reads the settings of the profile an active connection was activated from.
+pub(super) async fn profile_settings(
+    connection: &ActiveConnection<'_>,
+) -> Option<ConnectionSettings> {
+    let proxy = connection.inner();
+    let path = proxy
+        .get_property::<OwnedObjectPath>("Connection")
+        .await
+        .ok()?;
+
+    connection_settings(proxy.connection(), path).await.ok()
+}
+
+/// The SSID a Wi-Fi profile connects to.
+pub(super) fn wifi_ssid(settings: &ConnectionSettings) -> Option<SSID> {
+    setting_value::<Vec<u8>>(settings.get("802-11-wireless"), "ssid").map(SSID::from)
+}
+
+/// Collects gateways, IPv6 addresses and DNS configuration of an active connection.
+///
+/// DHCPv4 options fill in whatever the IPv4 configuration does not report.
+async fn ip_details(connection: &ActiveConnection<'_>) -> IpDetails {
+    let proxy = connection.inner();
+    let conn = proxy.connection();
+    let mut details = IpDetails::default();
+
+    let (ip4_path, ip6_path, dhcp4_path) = futures::join!(
+        proxy.get_property::<OwnedObjectPath>("Ip4Config"),
+        proxy.get_property::<OwnedObjectPath>("Ip6Config"),
+        proxy.get_property::<OwnedObjectPath>("Dhcp4Config"),
+    );
+
+    if let Some(ip4) = config_proxy(ip4_path, |path| Ip4ConfigProxy::new(conn, path)).await {
+        let (gateway, nameservers, searches, domains) = futures::join!(
+            ip4.gateway(),
+            ip4.nameserver_data(),
+            ip4.searches(),
+            ip4.domains()
+        );
+
+        details.ipv4_gateway = gateway.ok().and_then(|gateway| gateway.parse().ok());
+        details.dns_servers.extend(
+            nameservers
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|data| data.get("address")?.downcast_ref::<String>().ok())
+                .filter_map(|address| address.parse::<IpAddr>().ok()),
+        );
+        details.search_domains.extend(searches.unwrap_or_default());
+        details.search_domains.extend(domains.unwrap_or_default());
+    }
+
+    if let Some(ip6) = config_proxy(ip6_path, |path| Ip6ConfigProxy::new(conn, path)).await {
+        let (addresses, gateway, nameservers, searches, domains) = futures::join!(
+            ip6.address_data(),
+            ip6.gateway(),
+            ip6.nameservers(),
+            ip6.searches(),
+            ip6.domains()
+        );
+
+        details.ipv6_addresses = addresses
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|data| data.get("address")?.downcast_ref::<String>().ok())
+            .filter_map(|address| address.parse().ok())
+            .collect();
+        details.ipv6_gateway = gateway.ok().and_then(|gateway| gateway.parse().ok());
+        details.dns_servers.extend(
+            nameservers
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|bytes| <[u8; 16]>::try_from(bytes).ok())
+                .map(|bytes| IpAddr::V6(Ipv6Addr::from(bytes))),
+        );
+        details.search_domains.extend(searches.unwrap_or_default());
+        details.search_domains.extend(domains.unwrap_or_default());
+    }
+
+    if let Some(dhcp4) = config_proxy(dhcp4_path, |path| Dhcp4ConfigProxy::new(conn, path)).await {
+        let options = dhcp4.options().await.unwrap_or_default();
+        let option = |key: &str| -> Vec<String> {
+            options
+                .get(key)
+                .and_then(|value| value.downcast_ref::<String>().ok())
+                .map(|value| value.split_whitespace().map(String::from).collect())
+                .unwrap_or_default()
+        };
+
+        if details.ipv4_gateway.is_none() {
+            details.ipv4_gateway = option("routers").first().and_then(|r| r.parse().ok());
+        }
+
+        if !details.dns_servers.iter().any(IpAddr::is_ipv4) {
+            details.dns_servers.extend(
+                option("domain_name_servers")
+                    .iter()
+                    .filter_map(|address| address.parse::<IpAddr>().ok()),
+            );
         }
+
+        if details.search_domains.is_empty() {
+            details.search_domains = option("domain_search");
+            if details.search_domains.is_empty() {
+                details.search_domains = option("domain_name");
+            }
+        }
+    }
+
+    dedup_keep_order(&mut details.dns_servers);
+    dedup_keep_order(&mut details.search_domains);
+
+    details
+}
+
+/// Removes repeated values, keeping the first occurrence of each in place.
+fn dedup_keep_order<T: Clone + Eq + Hash>(values: &mut Vec<T>) {
+    let mut seen = HashSet::new();
+    values.retain(|value| seen.insert(value.clone()));
+}
+
+/// Builds a proxy for a configuration object, which NetworkManager reports as `/` when absent.
+async fn config_proxy<P, F>(
+    path: zbus::Result<OwnedObjectPath>,
+    new: impl FnOnce(OwnedObjectPath) -> F,
+) -> Option<P>
+where
+    F: Future<Output = zbus::Result<P>>,
+{
+    let path = path.ok().filter(|path| path.as_str() != "/")?;
+    new(path).await.ok()
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.IP4Config",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Ip4Config {
+    #[zbus(property)]
+    fn gateway(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn nameserver_data(&self) -> zbus::Result<Vec<HashMap<String, OwnedValue>>>;
+    #[zbus(property)]
+    fn searches(&self) -> zbus::Result<Vec<String>>;
+    #[zbus(property)]
+    fn domains(&self) -> zbus::Result<Vec<String>>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.IP6Config",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Ip6Config {
+    #[zbus(property)]
+    fn address_data(&self) -> zbus::Result<Vec<HashMap<String, OwnedValue>>>;
+    #[zbus(property)]
+    fn gateway(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn nameservers(&self) -> zbus::Result<Vec<Vec<u8>>>;
+    #[zbus(property)]
+    fn searches(&self) -> zbus::Result<Vec<String>>;
+    #[zbus(property)]
+    fn domains(&self) -> zbus::Result<Vec<String>>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.DHCP4Config",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Dhcp4Config {
+    #[zbus(property)]
+    fn options(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_keep_order() {
+        let mut dns_servers: Vec<IpAddr> = ["192.168.1.1", "fd00::1", "1.1.1.1", "192.168.1.1"]
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect();
+        dedup_keep_order(&mut dns_servers);
+        assert_eq!(
+            dns_servers,
+            ["192.168.1.1", "fd00::1", "1.1.1.1"]
+                .iter()
+                .map(|address| address.parse::<IpAddr>().unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        let mut domains = vec![
+            String::from("lan"),
+            String::from("corp"),
+            String::from("lan"),
+        ];
+        dedup_keep_order(&mut domains);
+        assert_eq!(domains, ["lan", "corp"]);
     }
 }
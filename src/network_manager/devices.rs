@@ -1,26 +1,52 @@
 // Copyright 2024 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
-use super::Event;
+use super::{Event, connection_settings, reason::DeviceStateReason, setting_value};
 pub use cosmic_dbus_networkmanager::interface::enums::{
     ActiveConnectionState, DeviceState, DeviceType,
 };
 
-use cosmic_dbus_networkmanager::nm::NetworkManager;
-use futures::{SinkExt, StreamExt};
+use cosmic_dbus_networkmanager::{
+    device::Device, interface::device::DeviceProxy, nm::NetworkManager,
+    settings::NetworkManagerSettings,
+};
+
+use futures::{SinkExt, StreamExt, future::AbortHandle, stream::BoxStream};
 use iced_futures::{self, Subscription, stream};
-use std::{fmt::Debug, hash::Hash, sync::Arc};
-use zbus::{Connection, zvariant::ObjectPath};
+use std::{collections::HashMap, fmt::Debug, hash::Hash, sync::Arc};
+use zbus::{
+    Connection,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+};
+
+const DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const WIRED_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wired";
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DeviceInfo {
     pub path: ObjectPath<'static>,
     pub device_type: DeviceType,
     pub interface: String,
+    /// The hardware address, absent for virtual devices such as loopback or tunnels.
+    pub hw_address: Option<String>,
     pub state: DeviceState,
+    /// Why the device entered its current state, such as a lost carrier or missing firmware.
+    pub state_reason: DeviceStateReason,
+    /// Whether NetworkManager controls the device.
+    pub managed: bool,
+    /// Whether NetworkManager may activate connections on the device on its own.
+    pub autoconnect: bool,
+    pub driver: String,
+    pub firmware_version: String,
+    /// Whether a cable is plugged in, for wired devices.
+    pub carrier: Option<bool>,
+    /// Link speed in Mb/s, for wired devices with a link.
+    pub speed: Option<u32>,
     pub active_connection: Option<(DeviceConnection, ActiveConnectionState)>,
     pub available_connections: Vec<DeviceConnection>,
     pub known_connections: Vec<KnownDeviceConnection>,
+    /// Devices attached to a bridge or bond.
+    pub ports: Vec<ObjectPath<'static>>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -36,16 +62,128 @@ pub struct KnownDeviceConnection {
     pub uuid: Arc<str>,
 }
 
+/// A change to one field of a [`DeviceInfo`]. Sent by [`subscription`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DeviceUpdate {
+    State(DeviceState, DeviceStateReason),
+    Managed(bool),
+    Autoconnect(bool),
+    Driver(String),
+    FirmwareVersion(String),
+    Carrier(Option<bool>),
+    Speed(Option<u32>),
+    ActiveConnection(Option<(DeviceConnection, ActiveConnectionState)>),
+    AvailableConnections(Vec<DeviceConnection>),
+    KnownConnections(Vec<KnownDeviceConnection>),
+    Ports(Vec<ObjectPath<'static>>),
+}
+
+impl DeviceInfo {
+    /// Applies an update from [`subscription`].
+    pub fn update(&mut self, update: DeviceUpdate) {
+        match update {
+            DeviceUpdate::State(state, reason) => {
+                self.state = state;
+                self.state_reason = reason;
+            }
+            DeviceUpdate::Managed(managed) => self.managed = managed,
+            DeviceUpdate::Autoconnect(autoconnect) => self.autoconnect = autoconnect,
+            DeviceUpdate::Driver(driver) => self.driver = driver,
+            DeviceUpdate::FirmwareVersion(version) => self.firmware_version = version,
+            DeviceUpdate::Carrier(carrier) => self.carrier = carrier,
+            DeviceUpdate::Speed(speed) => self.speed = speed,
+            DeviceUpdate::ActiveConnection(active) => self.active_connection = active,
+            DeviceUpdate::AvailableConnections(available) => self.available_connections = available,
+            DeviceUpdate::KnownConnections(known) => self.known_connections = known,
+            DeviceUpdate::Ports(ports) => self.ports = ports,
+        }
+    }
+
+    /// The updates which turn `self` into `other`.
+    fn diff(&self, other: &DeviceInfo) -> Vec<DeviceUpdate> {
+        let mut updates = Vec::new();
+
+        if (self.state, self.state_reason) != (other.state, other.state_reason) {
+            updates.push(DeviceUpdate::State(other.state, other.state_reason));
+        }
+
+        if self.managed != other.managed {
+            updates.push(DeviceUpdate::Managed(other.managed));
+        }
+
+        if self.autoconnect != other.autoconnect {
+            updates.push(DeviceUpdate::Autoconnect(other.autoconnect));
+        }
+
+        if self.driver != other.driver {
+            updates.push(DeviceUpdate::Driver(other.driver.clone()));
+        }
+
+        if self.firmware_version != other.firmware_version {
+            updates.push(DeviceUpdate::FirmwareVersion(
+                other.firmware_version.clone(),
+            ));
+        }
+
+        if self.carrier != other.carrier {
+            updates.push(DeviceUpdate::Carrier(other.carrier));
+        }
+
+        if self.speed != other.speed {
+            updates.push(DeviceUpdate::Speed(other.speed));
+        }
+
+        if self.active_connection != other.active_connection {
+            updates.push(DeviceUpdate::ActiveConnection(
+                other.active_connection.clone(),
+            ));
+        }
+
+        if self.available_connections != other.available_connections {
+            updates.push(DeviceUpdate::AvailableConnections(
+                other.available_connections.clone(),
+            ));
+        }
+
+        if self.known_connections != other.known_connections {
+            updates.push(DeviceUpdate::KnownConnections(
+                other.known_connections.clone(),
+            ));
+        }
+
+        if self.ports != other.ports {
+            updates.push(DeviceUpdate::Ports(other.ports.clone()));
+        }
+
+        updates
+    }
+}
+
 pub async fn list<'a>(
     conn: &'a zbus::Connection,
     device_type_filter: fn(DeviceType) -> bool,
 ) -> zbus::Result<Vec<DeviceInfo>> {
     let nm = NetworkManager::new(conn).await?;
-    let devices = nm.devices().await?;
 
     let (devices, nm_settings) = futures::try_join!(nm.devices(), nm.settings())?;
+    let connection_settings = &saved_settings(&nm_settings).await?;
 
-    let connection_settings: &Vec<_> = &futures::stream::FuturesOrdered::from_iter(
+    let device_iter = devices.into_iter().map(|device| async move {
+        device_info(&device, connection_settings, device_type_filter).await
+    });
+
+    let devices_info = futures::stream::FuturesOrdered::from_iter(device_iter)
+        .filter_map(|res| async move { res })
+        .collect::<Vec<DeviceInfo>>()
+        .await;
+
+    Ok(devices_info)
+}
+
+async fn saved_settings(
+    nm_settings: &NetworkManagerSettings<'_>,
+) -> zbus::Result<Vec<HashMap<String, HashMap<String, OwnedValue>>>> {
+    Ok(futures::stream::FuturesOrdered::from_iter(
         nm_settings
             .list_connections()
             .await?
@@ -54,137 +192,219 @@ pub async fn list<'a>(
     )
     .filter_map(|res| async move { res.ok() })
     .collect()
-    .await;
+    .await)
+}
 
-    let device_iter = devices.into_iter().map(|device| async move {
-        let (interface, hw_address, device_type, state, available_connections) =
-            futures::try_join!(
-                device.interface(),
-                device.hw_address(),
-                device.device_type(),
-                device.state(),
-                device.available_connections()
-            )
-            .ok()?;
+async fn device_info(
+    device: &Device<'_>,
+    connection_settings: &[HashMap<String, HashMap<String, OwnedValue>>],
+    device_type_filter: fn(DeviceType) -> bool,
+) -> Option<DeviceInfo> {
+    let (interface, hw_address, device_type) = futures::try_join!(
+        device.interface(),
+        device.hw_address(),
+        device.device_type(),
+    )
+    .ok()?;
 
-        if !device_type_filter(device_type) {
-            return None;
-        }
+    if !device_type_filter(device_type) {
+        return None;
+    }
 
-        if hw_address.is_empty() {
-            return None;
-        }
+    let known_connections = known_connections(connection_settings, &interface);
+
+    let mut info = DeviceInfo {
+        path: device.inner().path().to_owned(),
+        device_type,
+        interface,
+        hw_address: Some(hw_address).filter(|address| !address.is_empty()),
+        state: DeviceState::Unknown,
+        state_reason: DeviceStateReason::Unknown,
+        managed: false,
+        autoconnect: false,
+        driver: String::new(),
+        firmware_version: String::new(),
+        carrier: None,
+        speed: None,
+        active_connection: None,
+        known_connections,
+        available_connections: Vec::new(),
+        ports: Vec::new(),
+    };
 
-        let (active_connection, available_connections) = futures::join!(
-            async {
-                let connection = device.active_connection().await?;
+    refresh(device, &mut info).await?;
+    Some(info)
+}
 
-                let (id, uuid, state) =
-                    futures::try_join!(connection.id(), connection.uuid(), connection.state())?;
+/// The saved profiles bound to an interface by name.
+fn known_connections(
+    connection_settings: &[HashMap<String, HashMap<String, OwnedValue>>],
+    interface: &str,
+) -> Vec<KnownDeviceConnection> {
+    connection_settings
+        .iter()
+        .flat_map(|conn_settings| {
+            let connection = conn_settings.get("connection")?;
+
+            let interface_name = connection
+                .get("interface-name")?
+                .downcast_ref::<String>()
+                .ok()?;
+
+            if interface_name != interface {
+                return None;
+            }
+
+            let id = connection.get("id")?.downcast_ref::<String>().ok()?;
+            let uuid = connection.get("uuid")?.downcast_ref::<String>().ok()?;
+
+            Some(KnownDeviceConnection {
+                uuid: Arc::from(uuid),
+                id,
+            })
+        })
+        .collect()
+}
 
-                Ok::<_, zbus::Error>((
-                    DeviceConnection {
-                        id,
-                        uuid: Arc::from(uuid),
-                        path: connection.inner().path().to_owned(),
-                    },
-                    state,
-                ))
-            },
-            futures::stream::FuturesOrdered::from_iter(available_connections.into_iter().map(
-                |conn| async move {
-                    let path = conn.inner().path().to_owned();
-
-                    let settings = conn.get_settings().await.ok()?;
-
-                    let id = settings
-                        .get("connection")?
-                        .get("id")?
-                        .downcast_ref::<String>()
-                        .ok()?;
-
-                    let uuid = settings["connection"]
-                        .get("uuid")?
-                        .downcast_ref::<String>()
-                        .ok()?;
-
-                    Some(DeviceConnection {
-                        id,
-                        uuid: Arc::from(uuid),
-                        path,
-                    })
-                }
-            ),)
-            .filter_map(|res| async move { res })
-            .collect::<Vec<_>>()
-        );
-
-        let known_connections = connection_settings
-            .iter()
-            .flat_map(|conn_settings| {
-                let connection = conn_settings.get("connection")?;
-
-                let interface_name = connection
-                    .get("interface-name")?
-                    .downcast_ref::<String>()
-                    .ok()?;
-
-                if interface_name != interface {
-                    return None;
-                }
+/// Rereads the fields of a [`DeviceInfo`] which change while the device is in use.
+async fn refresh(device: &Device<'_>, info: &mut DeviceInfo) -> Option<()> {
+    let proxy = device.inner();
+
+    let (state, state_reason, managed, autoconnect, active_connection, available_paths) = futures::join!(
+        device.state(),
+        proxy.get_property::<(u32, u32)>("StateReason"),
+        proxy.get_property::<bool>("Managed"),
+        proxy.get_property::<bool>("Autoconnect"),
+        active_connection(device),
+        proxy.get_property::<Vec<OwnedObjectPath>>("AvailableConnections"),
+    );
+
+    // The driver is rebound and firmware reloaded when a device is reset.
+    let (driver, firmware_version) = futures::join!(
+        proxy.get_property::<String>("Driver"),
+        proxy.get_property::<String>("FirmwareVersion"),
+    );
+    info.driver = driver.unwrap_or_default();
+    info.firmware_version = firmware_version.unwrap_or_default();
+
+    info.state = state.ok()?;
+
+    // Reading the settings of every profile is only worth it when the set of profiles changed.
+    let available_paths = available_paths.ok()?;
+    if !info
+        .available_connections
+        .iter()
+        .map(|connection| connection.path.as_str())
+        .eq(available_paths.iter().map(|path| path.as_str()))
+    {
+        info.available_connections =
+            available_connections(proxy.connection(), available_paths).await;
+    }
 
-                let id = connection.get("id")?.downcast_ref::<String>().ok()?;
-                let uuid = connection.get("uuid")?.downcast_ref::<String>().ok()?;
+    info.active_connection = active_connection;
+    info.state_reason = state_reason
+        .map(|(_state, reason)| DeviceStateReason::from(reason))
+        .unwrap_or_default();
+    info.managed = managed.unwrap_or_default();
+    info.autoconnect = autoconnect.unwrap_or_default();
+
+    if matches!(info.device_type, DeviceType::Bond | DeviceType::Bridge) {
+        info.ports = proxy
+            .get_property::<Vec<OwnedObjectPath>>("Ports")
+            .await
+            .map(|ports| ports.into_iter().map(OwnedObjectPath::into_inner).collect())
+            .unwrap_or_default();
+    }
 
-                Some(KnownDeviceConnection {
-                    uuid: Arc::from(uuid),
-                    id,
-                })
-            })
-            .collect();
-
-        Some(DeviceInfo {
-            path: device.inner().path().to_owned(),
-            device_type,
-            interface,
-            state,
-            active_connection: active_connection.ok(),
-            known_connections,
-            available_connections,
-        })
-    });
+    if info.device_type == DeviceType::Ethernet {
+        let (carrier, speed) = match wired_proxy(device).await {
+            Ok(wired) => futures::join!(
+                wired.get_property::<bool>("Carrier"),
+                wired.get_property::<u32>("Speed"),
+            ),
+            Err(why) => (Err(why.clone()), Err(why)),
+        };
+
+        info.carrier = carrier.ok();
+        // NetworkManager reports a speed of 0 when it is unknown.
+        info.speed = speed.ok().filter(|speed| *speed != 0);
+    }
 
-    let devices_info = futures::stream::FuturesOrdered::from_iter(device_iter)
-        .filter_map(|res| async move { res })
-        .collect::<Vec<DeviceInfo>>()
-        .await;
+    Some(())
+}
 
-    Ok(devices_info)
+async fn wired_proxy(device: &Device<'_>) -> zbus::Result<zbus::Proxy<'static>> {
+    let proxy = device.inner();
+
+    zbus::Proxy::new(
+        proxy.connection(),
+        proxy.destination().to_owned(),
+        proxy.path().to_owned(),
+        WIRED_INTERFACE,
+    )
+    .await
 }
 
+async fn active_connection(
+    device: &Device<'_>,
+) -> Option<(DeviceConnection, ActiveConnectionState)> {
+    let connection = device.active_connection().await.ok()?;
+
+    let (id, uuid, state) =
+        futures::try_join!(connection.id(), connection.uuid(), connection.state()).ok()?;
+
+    Some((
+        DeviceConnection {
+            id,
+            uuid: Arc::from(uuid),
+            path: connection.inner().path().to_owned(),
+        },
+        state,
+    ))
+}
+
+async fn available_connections(
+    conn: &zbus::Connection,
+    paths: Vec<OwnedObjectPath>,
+) -> Vec<DeviceConnection> {
+    futures::stream::FuturesOrdered::from_iter(paths.into_iter().map(|path| async move {
+        let settings = connection_settings(conn, path.clone()).await.ok()?;
+        let connection = settings.get("connection");
+
+        Some(DeviceConnection {
+            id: setting_value(connection, "id")?,
+            uuid: Arc::from(setting_value::<String>(connection, "uuid")?),
+            path: path.into_inner(),
+        })
+    }))
+    .filter_map(|res| async move { res })
+    .collect()
+    .await
+}
+
+/// Follows the devices known to NetworkManager.
+///
+/// Each device is first sent as [`Event::DeviceAdded`], followed by an [`Event::DeviceChanged`]
+/// whenever one of the fields covered by [`DeviceUpdate`] changes, and an
+/// [`Event::DeviceRemoved`] once it is gone.
 pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
     id: I,
-    has_popup: bool,
     conn: Connection,
 ) -> iced_futures::Subscription<Event> {
     Subscription::run_with_id(
-        (id, has_popup),
+        id,
         stream::channel(50, move |output| async move {
-            watch(conn, has_popup, output).await;
+            watch(conn, output).await;
             futures::future::pending().await
         }),
     )
 }
 
-pub async fn watch(
-    conn: zbus::Connection,
-    has_popup: bool,
-    mut output: futures::channel::mpsc::Sender<Event>,
-) {
+pub async fn watch(conn: zbus::Connection, mut output: futures::channel::mpsc::Sender<Event>) {
     let mut state = State::Continue(conn);
 
     loop {
-        state = start_listening(state, has_popup, &mut output).await;
+        state = start_listening(state, &mut output).await;
     }
 }
 
@@ -194,9 +414,22 @@ pub enum State {
     Error,
 }
 
+struct WatchedDevice {
+    device: Device<'static>,
+    info: DeviceInfo,
+    handle: AbortHandle,
+}
+
+enum Message {
+    Added(ObjectPath<'static>),
+    Removed(ObjectPath<'static>),
+    Changed(ObjectPath<'static>),
+    /// A profile was added to or removed from the saved settings.
+    ConnectionsChanged,
+}
+
 async fn start_listening(
     state: State,
-    has_popup: bool,
     output: &mut futures::channel::mpsc::Sender<Event>,
 ) -> State {
     let conn = match state {
@@ -214,18 +447,329 @@ async fn start_listening(
         }
     };
 
-    let mut devices_changed = network_manager.receive_devices_changed().await;
+    // Subscribed to before listing the devices, so that none are missed in between.
+    let (added, removed) = match futures::try_join!(
+        network_manager.receive_device_added(),
+        network_manager.receive_device_removed(),
+    ) {
+        Ok(signals) => signals,
+        Err(why) => {
+            tracing::error!(?why, "failed to receive device signals");
+            return State::Error;
+        }
+    };
 
-    let secs = if has_popup { 4 } else { 60 };
+    let nm_settings = match network_manager.settings().await {
+        Ok(nm_settings) => nm_settings,
+        Err(why) => {
+            tracing::error!(?why, "failed to connect to network_manager settings");
+            return State::Error;
+        }
+    };
 
-    while let (Some(_change), _) = futures::future::join(
-        devices_changed.next(),
-        tokio::time::sleep(tokio::time::Duration::from_secs(secs)),
-    )
-    .await
-    {
-        _ = output.send(Event::Devices).await;
+    let (new_connection, connection_removed) = match futures::try_join!(
+        nm_settings.inner().receive_signal("NewConnection"),
+        nm_settings.inner().receive_signal("ConnectionRemoved"),
+    ) {
+        Ok(signals) => signals,
+        Err(why) => {
+            tracing::error!(?why, "failed to receive settings signals");
+            return State::Error;
+        }
+    };
+
+    let mut messages = futures::stream::SelectAll::<BoxStream<'static, Message>>::new();
+    messages.push(
+        futures::stream::select(new_connection, connection_removed)
+            .map(|_| Message::ConnectionsChanged)
+            .boxed(),
+    );
+    messages.push(
+        added
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                Some(Message::Added(args.device_path().to_owned()))
+            })
+            .boxed(),
+    );
+    messages.push(
+        removed
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                Some(Message::Removed(args.device_path().to_owned()))
+            })
+            .boxed(),
+    );
+
+    let mut watched = HashMap::<ObjectPath<'static>, WatchedDevice>::new();
+
+    let connection_settings = saved_settings(&nm_settings).await.unwrap_or_default();
+
+    for device in network_manager.devices().await.unwrap_or_default() {
+        watch_new_device(
+            device,
+            &connection_settings,
+            &mut watched,
+            &mut messages,
+            output,
+        )
+        .await;
+    }
+
+    while let Some(message) = messages.next().await {
+        match message {
+            Message::Added(path) => {
+                if watched.contains_key(&path) {
+                    continue;
+                }
+
+                let device = match DeviceProxy::builder(&conn).path(path) {
+                    Ok(builder) => match builder.build().await {
+                        Ok(proxy) => Device::from(proxy),
+                        Err(_) => continue,
+                    },
+                    Err(_) => continue,
+                };
+
+                let connection_settings = saved_settings(&nm_settings).await.unwrap_or_default();
+
+                watch_new_device(
+                    device,
+                    &connection_settings,
+                    &mut watched,
+                    &mut messages,
+                    output,
+                )
+                .await;
+            }
+
+            Message::Removed(path) => {
+                if let Some(old) = watched.remove(&path) {
+                    old.handle.abort();
+                    _ = output.send(Event::DeviceRemoved(path)).await;
+                }
+            }
+
+            Message::Changed(path) => {
+                let Some(watched) = watched.get_mut(&path) else {
+                    continue;
+                };
+
+                let mut info = watched.info.clone();
+                if refresh(&watched.device, &mut info).await.is_none() {
+                    continue;
+                }
+
+                for update in watched.info.diff(&info) {
+                    _ = output
+                        .send(Event::DeviceChanged(path.clone(), update))
+                        .await;
+                }
+
+                watched.info = info;
+            }
+
+            Message::ConnectionsChanged => {
+                let Ok(connection_settings) = saved_settings(&nm_settings).await else {
+                    continue;
+                };
+
+                for (path, watched) in &mut watched {
+                    let known = known_connections(&connection_settings, &watched.info.interface);
+                    if watched.info.known_connections == known {
+                        continue;
+                    }
+
+                    watched.info.known_connections = known.clone();
+                    _ = output
+                        .send(Event::DeviceChanged(
+                            path.clone(),
+                            DeviceUpdate::KnownConnections(known),
+                        ))
+                        .await;
+                }
+            }
+        }
     }
 
     State::Continue(conn)
 }
+
+/// Reports a device with [`Event::DeviceAdded`] and starts following its changes.
+async fn watch_new_device(
+    device: Device<'static>,
+    connection_settings: &[HashMap<String, HashMap<String, OwnedValue>>],
+    watched: &mut HashMap<ObjectPath<'static>, WatchedDevice>,
+    messages: &mut futures::stream::SelectAll<BoxStream<'static, Message>>,
+    output: &mut futures::channel::mpsc::Sender<Event>,
+) {
+    let Some(info) = device_info(&device, connection_settings, |_| true).await else {
+        return;
+    };
+
+    let (changes, handle) = watch_device(&device).await;
+    messages.push(changes.map(Message::Changed).boxed());
+
+    _ = output.send(Event::DeviceAdded(info.clone())).await;
+
+    watched.insert(
+        info.path.clone(),
+        WatchedDevice {
+            device,
+            info,
+            handle,
+        },
+    );
+}
+
+/// Yields the path of a device whenever one of the properties of a [`DeviceUpdate`] changes,
+/// until aborted.
+async fn watch_device(
+    device: &Device<'static>,
+) -> (BoxStream<'static, ObjectPath<'static>>, AbortHandle) {
+    let proxy = device.inner();
+
+    let mut changes = futures::stream::SelectAll::<BoxStream<'static, ()>>::new();
+    changes.push(property_changes::<u32>(proxy, "State").await);
+    changes.push(property_changes::<(u32, u32)>(proxy, "StateReason").await);
+    changes.push(property_changes::<bool>(proxy, "Managed").await);
+    changes.push(property_changes::<bool>(proxy, "Autoconnect").await);
+    changes.push(property_changes::<String>(proxy, "Driver").await);
+    changes.push(property_changes::<String>(proxy, "FirmwareVersion").await);
+    changes.push(property_changes::<OwnedObjectPath>(proxy, "ActiveConnection").await);
+    changes.push(property_changes::<Vec<OwnedObjectPath>>(proxy, "AvailableConnections").await);
+    changes.push(property_changes::<Vec<OwnedObjectPath>>(proxy, "Ports").await);
+
+    let wired = match device.device_type().await {
+        Ok(DeviceType::Ethernet) => wired_proxy(device).await.ok(),
+        _ => None,
+    };
+
+    if let Some(wired) = wired {
+        changes.push(property_changes::<bool>(&wired, "Carrier").await);
+        changes.push(property_changes::<u32>(&wired, "Speed").await);
+    }
+
+    let path = proxy.path().to_owned();
+    let changes = changes.map(move |()| path.clone());
+
+    let (changes, handle) = futures::stream::abortable(changes);
+    (changes.boxed(), handle)
+}
+
+async fn property_changes<T>(
+    proxy: &zbus::Proxy<'static>,
+    name: &'static str,
+) -> BoxStream<'static, ()>
+where
+    T: TryFrom<OwnedValue> + Unpin + Send + 'static,
+    T::Error: Into<zbus::Error>,
+{
+    proxy
+        .receive_property_changed::<T>(name)
+        .await
+        .map(|_| ())
+        .boxed()
+}
+
+/// Sets whether NetworkManager controls a device. An unmanaged device is left untouched.
+pub async fn set_managed(
+    conn: &zbus::Connection,
+    path: ObjectPath<'static>,
+    managed: bool,
+) -> zbus::Result<()> {
+    device_proxy(conn, path)
+        .await?
+        .set_property("Managed", managed)
+        .await
+        .map_err(zbus::Error::from)
+}
+
+/// Sets whether NetworkManager may activate connections on a device on its own.
+pub async fn set_autoconnect(
+    conn: &zbus::Connection,
+    path: ObjectPath<'static>,
+    autoconnect: bool,
+) -> zbus::Result<()> {
+    device_proxy(conn, path)
+        .await?
+        .set_property("Autoconnect", autoconnect)
+        .await
+        .map_err(zbus::Error::from)
+}
+
+/// Deactivates the connection on a device and keeps it from autoconnecting until it is
+/// activated again.
+pub async fn disconnect(conn: &zbus::Connection, path: ObjectPath<'static>) -> zbus::Result<()> {
+    device_proxy(conn, path)
+        .await?
+        .call::<_, _, ()>("Disconnect", &())
+        .await
+}
+
+async fn device_proxy(
+    conn: &zbus::Connection,
+    path: ObjectPath<'static>,
+) -> zbus::Result<zbus::Proxy<'static>> {
+    zbus::Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        path,
+        DEVICE_INTERFACE,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_update() {
+        let connection = DeviceConnection {
+            path: ObjectPath::from_static_str_unchecked(
+                "/org/freedesktop/NetworkManager/Settings/1",
+            ),
+            id: String::from("Wired connection 1"),
+            uuid: Arc::from("2a0c7e0e-46e4-4b8b-a8f4-4f0a7f3e7c1d"),
+        };
+
+        let old = DeviceInfo {
+            path: ObjectPath::from_static_str_unchecked(
+                "/org/freedesktop/NetworkManager/Devices/2",
+            ),
+            device_type: DeviceType::Ethernet,
+            interface: String::from("enp5s0"),
+            hw_address: Some(String::from("9C:6B:00:12:34:56")),
+            state: DeviceState::Unavailable,
+            state_reason: DeviceStateReason::CarrierLost,
+            managed: true,
+            autoconnect: true,
+            driver: String::from("r8169"),
+            firmware_version: String::from("rtl8125b-2_0.0.2 07/13/20"),
+            carrier: Some(false),
+            speed: None,
+            active_connection: None,
+            available_connections: vec![connection.clone()],
+            known_connections: Vec::new(),
+            ports: Vec::new(),
+        };
+
+        let mut new = old.clone();
+        new.state = DeviceState::Activated;
+        new.state_reason = DeviceStateReason::None;
+        new.carrier = Some(true);
+        new.speed = Some(1000);
+        new.active_connection = Some((connection, ActiveConnectionState::Activated));
+
+        let updates = old.diff(&new);
+        assert_eq!(updates.len(), 4);
+
+        let mut updated = old.clone();
+        for update in updates {
+            updated.update(update);
+        }
+        assert_eq!(updated, new);
+        assert!(new.diff(&updated).is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use secure_string::SecureString;
+use zbus::zvariant::Value;
+
+/// Outer EAP method used for 802.1X authentication.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EapMethod {
+    #[default]
+    Peap,
+    Ttls,
+    Tls,
+    Pwd,
+    Sim,
+}
+
+impl EapMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Peap => "peap",
+            Self::Ttls => "ttls",
+            Self::Tls => "tls",
+            Self::Pwd => "pwd",
+            Self::Sim => "sim",
+        }
+    }
+
+    /// Whether the method authenticates with the user's password.
+    pub fn uses_password(self) -> bool {
+        matches!(self, Self::Peap | Self::Ttls | Self::Pwd)
+    }
+
+    /// Whether the method tunnels a phase 2 authentication.
+    pub fn has_phase2(self) -> bool {
+        matches!(self, Self::Peap | Self::Ttls)
+    }
+}
+
+/// Inner authentication used by tunneled EAP methods.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Phase2Auth {
+    #[default]
+    Mschapv2,
+    Mschap,
+    Pap,
+    Chap,
+    Gtc,
+    Md5,
+}
+
+impl Phase2Auth {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mschapv2 => "mschapv2",
+            Self::Mschap => "mschap",
+            Self::Pap => "pap",
+            Self::Chap => "chap",
+            Self::Gtc => "gtc",
+            Self::Md5 => "md5",
+        }
+    }
+}
+
+/// Credentials for a WPA-Enterprise or wired 802.1X network.
+#[derive(Debug, Default, Clone)]
+pub struct EnterpriseCredentials {
+    pub method: EapMethod,
+    pub identity: String,
+    pub anonymous_identity: Option<String>,
+    /// Defaults to MSCHAPv2 for tunneled methods when unset.
+    pub phase2_auth: Option<Phase2Auth>,
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub private_key: Option<PathBuf>,
+    pub private_key_password: Option<SecureString>,
+    pub domain_suffix_match: Option<String>,
+}
+
+impl EnterpriseCredentials {
+    /// PEAP with MSCHAPv2, the most common configuration.
+    pub fn peap(identity: impl Into<String>) -> Self {
+        Self {
+            identity: identity.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Builds the `802-1x` setting for this configuration.
+    pub fn settings<'a>(&'a self, password: Option<&'a str>) -> HashMap<&'static str, Value<'a>> {
+        let mut settings = HashMap::from([
+            ("eap", Value::Array(vec![self.method.as_str()].into())),
+            ("identity", Value::Str(self.identity.as_str().into())),
+        ]);
+
+        if let Some(anonymous_identity) = self.anonymous_identity.as_deref() {
+            settings.insert("anonymous-identity", Value::Str(anonymous_identity.into()));
+        }
+
+        if self.method.has_phase2() {
+            let phase2 = self.phase2_auth.unwrap_or_default();
+            // TTLS carries GTC and MD5 as an inner EAP method rather than a plain one.
+            let key = match (self.method, phase2) {
+                (EapMethod::Ttls, Phase2Auth::Gtc | Phase2Auth::Md5) => "phase2-autheap",
+                _ => "phase2-auth",
+            };
+            settings.insert(key, Value::Str(phase2.as_str().into()));
+        }
+
+        if self.method.uses_password() {
+            settings.insert("password", Value::Str(password.unwrap_or("").into()));
+        }
+
+        if let Some(ca_cert) = self.ca_cert.as_deref() {
+            settings.insert("ca-cert", Value::from(cert_scheme_path(ca_cert)));
+        }
+
+        if let Some(domain) = self.domain_suffix_match.as_deref() {
+            settings.insert("domain-suffix-match", Value::Str(domain.into()));
+        }
+
+        if self.method == EapMethod::Tls {
+            if let Some(client_cert) = self.client_cert.as_deref() {
+                settings.insert("client-cert", Value::from(cert_scheme_path(client_cert)));
+            }
+
+            if let Some(private_key) = self.private_key.as_deref() {
+                settings.insert("private-key", Value::from(cert_scheme_path(private_key)));
+            }
+
+            if let Some(key_password) = self.private_key_password.as_ref() {
+                settings.insert(
+                    "private-key-password",
+                    Value::Str(key_password.unsecure().into()),
+                );
+            }
+        }
+
+        settings
+    }
+}
+
+/// NetworkManager expects certificate paths as a NUL-terminated `file://` byte string.
+fn cert_scheme_path(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut value = b"file://".to_vec();
+    value.extend_from_slice(path.as_os_str().as_bytes());
+    value.push(0);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttls_inner_eap_and_tls_only_keys() {
+        let creds = EnterpriseCredentials {
+            method: EapMethod::Ttls,
+            phase2_auth: Some(Phase2Auth::Gtc),
+            client_cert: Some(PathBuf::from("/etc/cert.pem")),
+            ..EnterpriseCredentials::peap("user")
+        };
+        let settings = creds.settings(Some("secret"));
+        assert!(settings.contains_key("phase2-autheap"));
+        assert!(!settings.contains_key("phase2-auth"));
+        assert!(!settings.contains_key("client-cert"));
+        assert!(settings.contains_key("password"));
+
+        let creds = EnterpriseCredentials {
+            method: EapMethod::Tls,
+            ..creds
+        };
+        let settings = creds.settings(Some("secret"));
+        assert!(settings.contains_key("client-cert"));
+        assert!(!settings.contains_key("password"));
+        assert!(!settings.contains_key("phase2-autheap"));
+    }
+
+    #[test]
+    fn test_cert_scheme_path() {
+        assert_eq!(
+            cert_scheme_path(Path::new("/a/b.pem")),
+            b"file:///a/b.pem\0".to_vec()
+        );
+    }
+}
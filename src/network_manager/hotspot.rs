@@ -0,0 +1,343 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ActivationWatch, ConnectionSettings, Error, SSID, UUID, active_connection_from_path,
+    available_wifi::Band, connection_settings, setting_value, settings_for_update,
+};
+use cosmic_dbus_networkmanager::{
+    active_connection::ActiveConnection,
+    device::{Device, SpecificDevice},
+    interface::enums::DeviceType,
+    nm::NetworkManager,
+    settings::{NetworkManagerSettings, connection::Connection},
+};
+use std::{collections::HashMap, net::IpAddr, time::SystemTime};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+
+const HOTSPOT_ID: &str = "Hotspot";
+
+/// Where NetworkManager keeps the leases of the DHCP server of shared connections.
+const DNSMASQ_LEASES_DIR: &str = "/var/lib/NetworkManager";
+
+/// `NM_802_11_MODE_AP`
+const NM_802_11_MODE_AP: u32 = 3;
+
+// Bits of `NMDeviceWifiCapabilities`.
+const WIFI_DEVICE_CAP_AP: u32 = 0x40;
+const WIFI_DEVICE_CAP_FREQ_VALID: u32 = 0x100;
+const WIFI_DEVICE_CAP_FREQ_2GHZ: u32 = 0x200;
+const WIFI_DEVICE_CAP_FREQ_5GHZ: u32 = 0x400;
+const WIFI_DEVICE_CAP_FREQ_6GHZ: u32 = 0x800;
+
+/// A running hotspot shared from one of the Wi-Fi devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotspot {
+    pub ssid: SSID,
+    pub uuid: UUID,
+    pub interface: String,
+}
+
+/// A station which was handed an address by the hotspot's DHCP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotspotClient {
+    pub hw_address: String,
+    pub address: IpAddr,
+    pub hostname: Option<String>,
+}
+
+/// Shares the network connection through an access point on the first capable Wi-Fi device.
+///
+/// A previously created hotspot profile is updated and reused.
+pub async fn start(
+    conn: &zbus::Connection,
+    ssid: &SSID,
+    password: &str,
+    band: Option<Band>,
+    channel: Option<u32>,
+) -> Result<(), Error> {
+    let nm = NetworkManager::new(conn).await?;
+    let band = band.or_else(|| channel.map(Band::from_channel));
+
+    // NetworkManager has no band setting to pin a hotspot to 6 GHz.
+    if band.is_some_and(|band| band.setting().is_none()) {
+        return Err(Error::HotspotUnsupported);
+    }
+
+    let device = capable_device(&nm, band).await?;
+    let settings = settings(ssid, password, band, channel);
+    let watch = ActivationWatch::new(&device).await?;
+
+    let active_conn = if let Some(connection) = saved_profile(conn).await? {
+        let existing = connection.get_settings().await?;
+        let mut update = settings_for_update(&existing);
+
+        for (name, setting) in settings {
+            let existing = update.entry(name).or_default();
+            if name == "802-11-wireless" {
+                existing.retain(|key, _| !matches!(*key, "band" | "channel"));
+            }
+            existing.extend(setting);
+        }
+
+        connection.update(update).await?;
+        nm.activate_connection(&connection, &device).await?
+    } else {
+        let (_, active_conn) = nm
+            .add_and_activate_connection(
+                settings,
+                device.inner().path(),
+                &ObjectPath::from_static_str_unchecked("/"),
+            )
+            .await?;
+        active_connection_from_path(conn, active_conn).await?
+    };
+
+    watch.wait(&active_conn).await
+}
+
+/// Deactivates any active hotspot.
+pub async fn stop(conn: &zbus::Connection) -> Result<(), Error> {
+    let nm = NetworkManager::new(conn).await?;
+
+    for active in nm.active_connections().await? {
+        if access_point_device(&active).await.is_some() {
+            nm.deactivate_connection(&active).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the active hotspot, if any.
+pub async fn state(conn: &zbus::Connection, nm: &NetworkManager<'_>) -> Option<Hotspot> {
+    for active in nm.active_connections().await.ok()? {
+        let Some(device) = access_point_device(&active).await else {
+            continue;
+        };
+
+        let path = active
+            .inner()
+            .get_property::<OwnedObjectPath>("Connection")
+            .await
+            .ok()?;
+        let settings = connection_settings(conn, path).await.ok()?;
+
+        let ssid = setting_value::<Vec<u8>>(settings.get("802-11-wireless"), "ssid")
+            .map(SSID::from)
+            .unwrap_or_default();
+
+        return Some(Hotspot {
+            ssid,
+            uuid: UUID::from(active.uuid().await.unwrap_or_default()),
+            interface: device.interface().await.unwrap_or_default(),
+        });
+    }
+
+    None
+}
+
+/// Lists the stations holding a DHCP lease from the hotspot on `interface`.
+///
+/// Leases outlive the association of a station, so a client which left recently is still listed
+/// until its lease expires.
+pub async fn clients(interface: &str) -> std::io::Result<Vec<HotspotClient>> {
+    let path = format!("{DNSMASQ_LEASES_DIR}/dnsmasq-{interface}.leases");
+
+    let leases = tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+        .await
+        .map_err(std::io::Error::other)?;
+
+    let leases = match leases {
+        Ok(leases) => leases,
+        // NetworkManager creates the file with the first lease.
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+
+    Ok(leased_clients(&leases, now))
+}
+
+/// Reads the unexpired leases of a dnsmasq lease file.
+fn leased_clients(leases: &str, now: u64) -> Vec<HotspotClient> {
+    leases
+        .lines()
+        .filter_map(|line| {
+            // <expiry> <hw address> <ip address> <hostname or *> <client id or *>
+            let mut columns = line.split_whitespace();
+            let expiry = columns.next()?.parse::<u64>().ok()?;
+            let hw_address = columns.next()?;
+            let address = columns.next()?.parse().ok()?;
+            let hostname = columns.next().filter(|hostname| *hostname != "*");
+
+            // An expiry of 0 marks an infinite lease.
+            if expiry != 0 && expiry <= now {
+                return None;
+            }
+
+            Some(HotspotClient {
+                hw_address: hw_address.to_owned(),
+                address,
+                hostname: hostname.map(str::to_owned),
+            })
+        })
+        .collect()
+}
+
+/// The device an active connection runs an access point on, if it does.
+async fn access_point_device<'a>(active: &ActiveConnection<'a>) -> Option<Device<'a>> {
+    let connection_type = active.inner().get_property::<String>("Type").await.ok()?;
+    if connection_type != "802-11-wireless" {
+        return None;
+    }
+
+    let device = active.devices().await.ok()?.into_iter().next()?;
+
+    let Ok(Some(SpecificDevice::Wireless(wireless))) = device.downcast_to_device().await else {
+        return None;
+    };
+
+    let mode = wireless.inner().get_property::<u32>("Mode").await.ok()?;
+    (mode == NM_802_11_MODE_AP).then_some(device)
+}
+
+async fn capable_device<'a>(
+    nm: &NetworkManager<'a>,
+    band: Option<Band>,
+) -> Result<Device<'a>, Error> {
+    for device in nm.devices().await? {
+        if !matches!(device.device_type().await, Ok(DeviceType::Wifi)) {
+            continue;
+        }
+
+        let Ok(Some(SpecificDevice::Wireless(wireless))) = device.downcast_to_device().await else {
+            continue;
+        };
+
+        let capabilities = wireless
+            .inner()
+            .get_property::<u32>("WirelessCapabilities")
+            .await
+            .unwrap_or_default();
+
+        if capabilities & WIFI_DEVICE_CAP_AP == 0 {
+            continue;
+        }
+
+        // Band support is only known when the driver reports valid frequencies.
+        let band_supported = match band {
+            _ if capabilities & WIFI_DEVICE_CAP_FREQ_VALID == 0 => true,
+            Some(Band::TwoPointFourGhz) => capabilities & WIFI_DEVICE_CAP_FREQ_2GHZ != 0,
+            Some(Band::FiveGhz) => capabilities & WIFI_DEVICE_CAP_FREQ_5GHZ != 0,
+            Some(Band::SixGhz) => capabilities & WIFI_DEVICE_CAP_FREQ_6GHZ != 0,
+            None => true,
+        };
+
+        if band_supported {
+            return Ok(device);
+        }
+    }
+
+    Err(Error::HotspotUnsupported)
+}
+
+/// Finds a saved hotspot profile to reuse.
+async fn saved_profile(conn: &zbus::Connection) -> Result<Option<Connection<'static>>, Error> {
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    for connection in nm_settings.list_connections().await? {
+        let Ok(settings) = connection.get_settings().await else {
+            continue;
+        };
+
+        if is_hotspot(&settings) {
+            return Ok(Some(connection));
+        }
+    }
+
+    Ok(None)
+}
+
+fn is_hotspot(settings: &ConnectionSettings) -> bool {
+    setting_value::<String>(settings.get("802-11-wireless"), "mode").as_deref() == Some("ap")
+        && setting_value::<String>(settings.get("ipv4"), "method").as_deref() == Some("shared")
+}
+
+fn settings<'a>(
+    ssid: &'a SSID,
+    password: &'a str,
+    band: Option<Band>,
+    channel: Option<u32>,
+) -> HashMap<&'static str, HashMap<&'static str, Value<'a>>> {
+    let mut wireless = HashMap::from([
+        ("ssid", Value::Array(ssid.as_bytes().into())),
+        ("mode", Value::from("ap")),
+        ("security", Value::from("802-11-wireless-security")),
+    ]);
+
+    if let Some(band) = band.and_then(Band::setting) {
+        wireless.insert("band", Value::from(band));
+
+        if let Some(channel) = channel {
+            wireless.insert("channel", Value::U32(channel));
+        }
+    }
+
+    HashMap::from([
+        (
+            "connection",
+            HashMap::from([
+                ("id", Value::from(HOTSPOT_ID)),
+                ("type", Value::from("802-11-wireless")),
+                ("autoconnect", Value::Bool(false)),
+            ]),
+        ),
+        ("802-11-wireless", wireless),
+        (
+            "802-11-wireless-security",
+            HashMap::from([
+                ("key-mgmt", Value::from("wpa-psk")),
+                ("psk", Value::from(password)),
+                ("proto", Value::from(vec!["rsn"])),
+                ("pairwise", Value::from(vec!["ccmp"])),
+                ("group", Value::from(vec!["ccmp"])),
+            ]),
+        ),
+        ("ipv4", HashMap::from([("method", Value::from("shared"))])),
+        ("ipv6", HashMap::from([("method", Value::from("ignore"))])),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leased_clients() {
+        let leases = "\
+1697541200 aa:bb:cc:dd:ee:01 10.42.0.23 phone 01:aa:bb:cc:dd:ee:01
+1697537600 aa:bb:cc:dd:ee:02 10.42.0.57 * 01:aa:bb:cc:dd:ee:02
+0 aa:bb:cc:dd:ee:03 10.42.0.61 printer *
+";
+        assert_eq!(
+            leased_clients(leases, 1697540000),
+            [
+                HotspotClient {
+                    hw_address: String::from("aa:bb:cc:dd:ee:01"),
+                    address: IpAddr::from([10, 42, 0, 23]),
+                    hostname: Some(String::from("phone")),
+                },
+                HotspotClient {
+                    hw_address: String::from("aa:bb:cc:dd:ee:03"),
+                    address: IpAddr::from([10, 42, 0, 61]),
+                    hostname: Some(String::from("printer")),
+                },
+            ]
+        );
+        assert!(leased_clients("", 0).is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, Hash, PartialOrd, Ord)]
 pub struct HwAddress {
     address: u64,
 }
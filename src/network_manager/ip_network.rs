@@ -0,0 +1,60 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// An IP address with its prefix length, written as `address/prefix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    pub address: IpAddr,
+    pub prefix: u8,
+}
+
+impl IpNetwork {
+    /// A network containing only the given address.
+    pub fn host(address: IpAddr) -> Self {
+        Self {
+            address,
+            prefix: max_prefix(address),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidIpNetwork;
+
+    /// Parses `address/prefix`, treating a bare address as a single host.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let Some((address, prefix)) = s.split_once('/') else {
+            return s.parse().map(Self::host).map_err(|_| InvalidIpNetwork);
+        };
+
+        let address: IpAddr = address.parse().map_err(|_| InvalidIpNetwork)?;
+        let prefix: u8 = prefix.parse().map_err(|_| InvalidIpNetwork)?;
+
+        if prefix > max_prefix(address) {
+            return Err(InvalidIpNetwork);
+        }
+
+        Ok(Self { address, prefix })
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("invalid IP network")]
+pub struct InvalidIpNetwork;
+
+fn max_prefix(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
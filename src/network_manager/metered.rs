@@ -0,0 +1,81 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{Error, Event, find_connection, profile::Metered, reapply, settings_for_update};
+use cosmic_dbus_networkmanager::nm::NetworkManager;
+use futures::{SinkExt, StreamExt};
+use iced_futures::{Subscription, stream};
+use std::{fmt::Debug, hash::Hash};
+use zbus::{Connection, zvariant::Value};
+
+#[derive(Debug, Clone)]
+pub enum State {
+    Continue(Connection),
+    Error,
+}
+
+/// Follows whether the primary connection is metered, emitting [`Event::Metered`] on each change.
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: Connection,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+pub async fn watch(conn: zbus::Connection, mut output: futures::channel::mpsc::Sender<Event>) {
+    let mut state = State::Continue(conn);
+
+    loop {
+        state = start_listening(state, &mut output).await;
+    }
+}
+
+async fn start_listening(
+    state: State,
+    output: &mut futures::channel::mpsc::Sender<Event>,
+) -> State {
+    let conn = match state {
+        State::Continue(conn) => conn,
+        State::Error => futures::future::pending().await,
+    };
+
+    let network_manager = match NetworkManager::new(&conn).await {
+        Ok(n) => n,
+        Err(why) => {
+            tracing::error!(why = why.to_string(), "Failed to connect to NetworkManager");
+            return State::Error;
+        }
+    };
+
+    let mut metered_changed = network_manager.receive_metered_changed().await;
+
+    while let Some(change) = metered_changed.next().await {
+        if let Ok(metered) = change.get().await {
+            _ = output.send(Event::Metered(Metered::from(metered))).await;
+        }
+    }
+
+    State::Continue(conn)
+}
+
+/// Sets `connection.metered` on a profile, reapplying it to any device it is active on.
+pub async fn set(conn: &zbus::Connection, uuid: &str, metered: Metered) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    update
+        .entry("connection")
+        .or_default()
+        .insert("metered", Value::I32(metered.setting()));
+
+    connection.update(update).await?;
+
+    reapply(conn, uuid).await
+}
@@ -0,0 +1,438 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Cellular modems, as managed by ModemManager.
+//!
+//! Modem details are read from ModemManager's object manager, so every function here works
+//! against any bus connection which serves the `org.freedesktop.ModemManager1` name. Activating
+//! mobile broadband connections is left to NetworkManager, which exposes each modem as a
+//! [`DeviceType::Modem`] device.
+
+use super::{Error, Event, connection_settings, setting_value, settings_for_update};
+use cosmic_dbus_networkmanager::{
+    interface::enums::DeviceType, nm::NetworkManager, settings::NetworkManagerSettings,
+};
+use futures::{SinkExt, StreamExt};
+use iced_futures::{Subscription, stream};
+use secure_string::SecureString;
+use std::{collections::HashMap, fmt::Debug, hash::Hash};
+use zbus::{
+    Connection, MatchRule, MessageStream,
+    fdo::ObjectManagerProxy,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+const MM_SERVICE: &str = "org.freedesktop.ModemManager1";
+const MM_PATH: &str = "/org/freedesktop/ModemManager1";
+const MM_MODEM: &str = "org.freedesktop.ModemManager1.Modem";
+const MM_MODEM_3GPP: &str = "org.freedesktop.ModemManager1.Modem.Modem3gpp";
+const MM_SIM: &str = "org.freedesktop.ModemManager1.Sim";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Modem {
+    /// ModemManager object path, which NetworkManager reports as the modem device's `Udi`.
+    pub path: ObjectPath<'static>,
+    pub manufacturer: String,
+    pub model: String,
+    pub state: ModemState,
+    /// Name of the network operator the modem is registered with.
+    pub operator: Option<String>,
+    pub access_technology: AccessTechnology,
+    /// Signal quality in percent.
+    pub signal_quality: u8,
+    pub sim_lock: SimLock,
+}
+
+/// `MMModemState`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ModemState {
+    Failed,
+    #[default]
+    Unknown,
+    Initializing,
+    /// The SIM must be unlocked before the modem can be enabled.
+    Locked,
+    Disabled,
+    Disabling,
+    Enabling,
+    Enabled,
+    Searching,
+    Registered,
+    Disconnecting,
+    Connecting,
+    Connected,
+}
+
+impl From<i32> for ModemState {
+    fn from(state: i32) -> Self {
+        match state {
+            -1 => Self::Failed,
+            1 => Self::Initializing,
+            2 => Self::Locked,
+            3 => Self::Disabled,
+            4 => Self::Disabling,
+            5 => Self::Enabling,
+            6 => Self::Enabled,
+            7 => Self::Searching,
+            8 => Self::Registered,
+            9 => Self::Disconnecting,
+            10 => Self::Connecting,
+            11 => Self::Connected,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The most capable radio generation in use, from `MMModemAccessTechnology` flags.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessTechnology {
+    #[default]
+    Unknown,
+    /// GSM, GPRS and EDGE.
+    TwoG,
+    /// UMTS, HSPA and CDMA2000.
+    ThreeG,
+    /// LTE, including LTE Cat-M and NB-IoT.
+    FourG,
+    /// 5G NR.
+    FiveG,
+}
+
+impl AccessTechnology {
+    pub fn from_bits(bits: u32) -> Self {
+        (0..u32::BITS)
+            .filter(|bit| bits & (1 << bit) != 0)
+            .map(|bit| match bit {
+                1..=4 => Self::TwoG,
+                5..=13 => Self::ThreeG,
+                14 | 16 | 17 => Self::FourG,
+                15 => Self::FiveG,
+                _ => Self::Unknown,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// The lock which must be cleared before the modem can be used, from `MMModemLock`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SimLock {
+    #[default]
+    Unknown,
+    Unlocked,
+    Pin,
+    /// Too many wrong PINs were entered; the PUK from the carrier is needed.
+    Puk,
+    /// Any other PIN or PUK, such as those of a network or service provider lock.
+    Other,
+}
+
+impl From<u32> for SimLock {
+    fn from(lock: u32) -> Self {
+        match lock {
+            0 => Self::Unknown,
+            1 => Self::Unlocked,
+            2 => Self::Pin,
+            4 => Self::Puk,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Access point name settings of a mobile broadband profile.
+#[derive(Debug, Default, Clone)]
+pub struct Apn {
+    pub apn: String,
+    pub username: Option<String>,
+    pub password: Option<SecureString>,
+}
+
+/// Lists the modems known to ModemManager.
+pub async fn list(conn: &zbus::Connection) -> Result<Vec<Modem>, Error> {
+    let object_manager = ObjectManagerProxy::builder(conn)
+        .destination(MM_SERVICE)?
+        .path(MM_PATH)?
+        .build()
+        .await?;
+
+    let objects = object_manager
+        .get_managed_objects()
+        .await
+        .map_err(zbus::Error::from)?;
+
+    let mut modems: Vec<Modem> = objects
+        .into_iter()
+        .filter_map(|(path, interfaces)| {
+            let interfaces: HashMap<&str, &HashMap<String, OwnedValue>> = interfaces
+                .iter()
+                .map(|(name, properties)| (name.as_str(), properties))
+                .collect();
+
+            modem(path, &interfaces)
+        })
+        .collect();
+
+    modems.sort_by(|a, b| a.path.as_str().cmp(b.path.as_str()));
+    Ok(modems)
+}
+
+fn modem(
+    path: OwnedObjectPath,
+    interfaces: &HashMap<&str, &HashMap<String, OwnedValue>>,
+) -> Option<Modem> {
+    let modem = Some(*interfaces.get(MM_MODEM)?);
+
+    let three_gpp = interfaces.get(MM_MODEM_3GPP).copied();
+
+    Some(Modem {
+        path: path.into_inner(),
+        manufacturer: setting_value(modem, "Manufacturer").unwrap_or_default(),
+        model: setting_value(modem, "Model").unwrap_or_default(),
+        state: setting_value::<i32>(modem, "State")
+            .map(ModemState::from)
+            .unwrap_or_default(),
+        operator: setting_value::<String>(three_gpp, "OperatorName")
+            .filter(|name| !name.is_empty()),
+        access_technology: setting_value::<u32>(modem, "AccessTechnologies")
+            .map(AccessTechnology::from_bits)
+            .unwrap_or_default(),
+        signal_quality: setting_value::<(u32, bool)>(modem, "SignalQuality")
+            .map_or(0, |(quality, _recent)| quality.min(100) as u8),
+        sim_lock: setting_value::<u32>(modem, "UnlockRequired")
+            .map(SimLock::from)
+            .unwrap_or_default(),
+    })
+}
+
+/// Reports the modems with [`Event::Modems`] whenever ModemManager announces a change.
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: Connection,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+pub async fn watch(conn: zbus::Connection, mut output: futures::channel::mpsc::Sender<Event>) {
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .sender(MM_SERVICE)
+        .and_then(|rule| rule.path_namespace(MM_PATH))
+        .map(|rule| rule.build());
+
+    let mut signals = match rule {
+        Ok(rule) => match MessageStream::for_match_rule(rule, &conn, None).await {
+            Ok(signals) => signals,
+            Err(why) => {
+                tracing::error!(?why, "failed to listen for ModemManager signals");
+                return;
+            }
+        },
+        Err(why) => {
+            tracing::error!(?why, "invalid ModemManager match rule");
+            return;
+        }
+    };
+
+    loop {
+        match list(&conn).await {
+            Ok(modems) => _ = output.send(Event::Modems(modems)).await,
+            Err(why) => tracing::debug!(?why, "failed to list modems"),
+        }
+
+        if signals.next().await.is_none() {
+            break;
+        }
+
+        // Changes arrive in bursts; coalesce them into one update.
+        while let Ok(Some(_)) =
+            tokio::time::timeout(tokio::time::Duration::from_millis(250), signals.next()).await
+        {
+        }
+    }
+}
+
+/// Powers the modem's radio on or off.
+pub async fn set_enabled(
+    conn: &zbus::Connection,
+    modem: &ObjectPath<'_>,
+    enable: bool,
+) -> Result<(), Error> {
+    let proxy = zbus::Proxy::new(conn, MM_SERVICE, modem.to_owned(), MM_MODEM).await?;
+    proxy.call::<_, _, ()>("Enable", &(enable,)).await?;
+    Ok(())
+}
+
+/// Unlocks the SIM card of a modem with its PIN.
+pub async fn unlock(
+    conn: &zbus::Connection,
+    modem: &ObjectPath<'_>,
+    pin: &str,
+) -> Result<(), Error> {
+    let proxy = zbus::Proxy::new(conn, MM_SERVICE, modem.to_owned(), MM_MODEM).await?;
+    let sim = proxy.get_property::<OwnedObjectPath>("Sim").await?;
+
+    if sim.as_str() == "/" {
+        return Err(Error::NoSim);
+    }
+
+    let sim = zbus::Proxy::new(conn, MM_SERVICE, sim, MM_SIM).await?;
+    sim.call::<_, _, ()>("SendPin", &(pin,)).await?;
+    Ok(())
+}
+
+/// Sets the APN of the modem's mobile broadband profile, creating one if there is none.
+///
+/// Returns the UUID of the profile.
+pub async fn set_apn(
+    conn: &zbus::Connection,
+    modem: &ObjectPath<'_>,
+    apn: &Apn,
+) -> Result<String, Error> {
+    let nm = NetworkManager::new(conn).await?;
+
+    let mut modem_device = None;
+    for device in nm.devices().await? {
+        if !matches!(device.device_type().await, Ok(DeviceType::Modem)) {
+            continue;
+        }
+
+        let udi = device.inner().get_property::<String>("Udi").await;
+        if udi.as_deref() == Ok(modem.as_str()) {
+            modem_device = Some(device);
+            break;
+        }
+    }
+
+    let Some(device) = modem_device else {
+        return Err(Error::ModemNotFound);
+    };
+
+    let mut gsm = HashMap::from([("apn", Value::from(apn.apn.as_str()))]);
+    if let Some(username) = apn.username.as_deref() {
+        gsm.insert("username", Value::from(username));
+    }
+    if let Some(password) = apn.password.as_ref() {
+        gsm.insert("password", Value::from(password.unsecure()));
+    }
+
+    // Update the profile NetworkManager would pick for this modem.
+    for connection in device.available_connections().await.unwrap_or_default() {
+        let Ok(settings) = connection.get_settings().await else {
+            continue;
+        };
+
+        let Some(uuid) = setting_value::<String>(settings.get("connection"), "uuid") else {
+            continue;
+        };
+
+        if setting_value::<String>(settings.get("connection"), "type").as_deref() != Some("gsm") {
+            continue;
+        }
+
+        let mut update = settings_for_update(&settings);
+        let setting = update.entry("gsm").or_default();
+        setting.retain(|key, _| !matches!(*key, "apn" | "username" | "password"));
+        setting.extend(gsm);
+
+        connection.update(update).await?;
+        return Ok(uuid);
+    }
+
+    let settings = HashMap::from([
+        (
+            "connection",
+            HashMap::from([
+                ("id", Value::from("Mobile Broadband")),
+                ("type", Value::from("gsm")),
+                ("autoconnect", Value::Bool(true)),
+            ]),
+        ),
+        ("gsm", gsm),
+        ("ipv4", HashMap::from([("method", Value::from("auto"))])),
+        ("ipv6", HashMap::from([("method", Value::from("auto"))])),
+    ]);
+
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+    let path: OwnedObjectPath = nm_settings
+        .inner()
+        .call("AddConnection", &(settings,))
+        .await?;
+
+    let settings = connection_settings(conn, path).await?;
+    setting_value::<String>(settings.get("connection"), "uuid").ok_or(Error::ConnectionNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_technology_from_bits() {
+        // MM_MODEM_ACCESS_TECHNOLOGY_UNKNOWN
+        assert_eq!(AccessTechnology::from_bits(0), AccessTechnology::Unknown);
+        // EDGE
+        assert_eq!(AccessTechnology::from_bits(1 << 4), AccessTechnology::TwoG);
+        // GSM | HSPA_PLUS
+        assert_eq!(
+            AccessTechnology::from_bits(1 << 1 | 1 << 9),
+            AccessTechnology::ThreeG
+        );
+        // LTE | 5GNR, as reported for non-standalone 5G
+        assert_eq!(
+            AccessTechnology::from_bits(1 << 14 | 1 << 15),
+            AccessTechnology::FiveG
+        );
+    }
+
+    fn properties<const N: usize>(entries: [(&str, Value<'_>); N]) -> HashMap<String, OwnedValue> {
+        entries
+            .into_iter()
+            .map(|(name, value)| (String::from(name), value.try_to_owned().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_modem() {
+        let modem_path = "/org/freedesktop/ModemManager1/Modem/0";
+        let properties_modem = properties([
+            ("Manufacturer", Value::from("Quectel")),
+            ("Model", Value::from("EM05-G")),
+            ("State", Value::from(8i32)),
+            ("AccessTechnologies", Value::from(1u32 << 14)),
+            ("SignalQuality", Value::from((72u32, true))),
+            ("UnlockRequired", Value::from(1u32)),
+        ]);
+        let properties_3gpp = properties([("OperatorName", Value::from("Example Mobile"))]);
+        let interfaces = HashMap::from([
+            (MM_MODEM, &properties_modem),
+            (MM_MODEM_3GPP, &properties_3gpp),
+        ]);
+
+        assert_eq!(
+            modem(OwnedObjectPath::try_from(modem_path).unwrap(), &interfaces),
+            Some(Modem {
+                path: ObjectPath::from_static_str_unchecked(modem_path),
+                manufacturer: String::from("Quectel"),
+                model: String::from("EM05-G"),
+                state: ModemState::Registered,
+                operator: Some(String::from("Example Mobile")),
+                access_technology: AccessTechnology::FourG,
+                signal_quality: 72,
+                sim_lock: SimLock::Unlocked,
+            })
+        );
+
+        // Objects without the modem interface, such as SIMs, are not modems.
+        let interfaces = HashMap::from([(MM_SIM, &properties_3gpp)]);
+        assert_eq!(
+            modem(OwnedObjectPath::try_from(modem_path).unwrap(), &interfaces),
+            None
+        );
+    }
+}
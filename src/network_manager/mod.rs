@@ -3,23 +3,44 @@
 
 pub mod active_conns;
 pub mod available_wifi;
+pub mod connectivity;
 pub mod current_networks;
 pub mod devices;
+pub mod eap;
+pub mod hotspot;
 pub mod hw_address;
+pub mod ip_network;
+pub mod metered;
+pub mod mobile_broadband;
+pub mod profile;
+pub mod proxy;
+pub mod reason;
+pub mod saved_connections;
+pub mod secret_agent;
+pub mod ssid;
+pub mod statistics;
+pub mod virtual_interface;
+pub mod vpn;
+pub mod wired;
+pub mod wireguard;
 pub mod wireless_enabled;
+pub mod wps;
 
-use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::Arc, time::Duration};
 
-use available_wifi::NetworkType;
+use available_wifi::{Band, NetworkType};
 pub use cosmic_dbus_networkmanager as dbus;
+use dbus::settings::connection::Connection;
 pub use dbus::settings::connection::Settings;
+use eap::EnterpriseCredentials;
 
+use crate::airplane_mode::rfkill;
 use cosmic_dbus_networkmanager::{
     active_connection::ActiveConnection,
     device::SpecificDevice,
     interface::{
         active_connection::ActiveConnectionProxy,
-        enums::{self, ActiveConnectionState, DeviceType, NmConnectivityState},
+        enums::{ActiveConnectionState, DeviceType, NmConnectivityState},
     },
     nm::NetworkManager,
     settings::NetworkManagerSettings,
@@ -30,32 +51,76 @@ use futures::{
 };
 use hw_address::HwAddress;
 use iced_futures::{Subscription, stream};
+use profile::{ConnectionProfile, Metered};
+use reason::{ActivationError, ActivationWatch};
 use secure_string::SecureString;
-use tokio::process::Command;
-use zbus::zvariant::{self, ObjectPath, Value};
+use wireguard::WireGuardConfig;
+use zbus::zvariant::{self, ObjectPath, OwnedObjectPath, OwnedValue, Value};
 
 use self::{
     available_wifi::{AccessPoint, handle_wireless_device},
     current_networks::{ActiveConnectionInfo, active_connections},
 };
 
-pub type SSID = Arc<str>;
+pub type SSID = ssid::Ssid;
 pub type UUID = Arc<str>;
 
+type ConnectionSettings = HashMap<String, HashMap<String, OwnedValue>>;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("access point not found")]
     AccessPointNotFound,
-    #[error("failed to list bluetooth devices with rfkill")]
+    #[error("failed to block bluetooth devices with rfkill: {0}")]
+    BluetoothRfkillBlock(std::io::Error),
+    #[error("failed to list bluetooth devices with rfkill: {0}")]
     BluetoothRfkillList(std::io::Error),
+    #[error("failed to activate connection: {0}")]
+    Activation(ActivationError),
     #[error("failed to activate connection")]
     ConnectionActivate,
+    #[error("connection profile not found")]
+    ConnectionNotFound,
+    #[error("enterprise network requires credentials")]
+    EnterpriseCredentialsRequired,
+    #[error("no wifi device supports access point mode")]
+    HotspotUnsupported,
+    #[error("invalid proxy server or host pattern: {0}")]
+    InvalidProxy(String),
+    #[error("VLAN ID {0} is out of range")]
+    InvalidVlanId(u16),
+    #[error("modem not found")]
+    ModemNotFound,
+    #[error("modem has no SIM card")]
+    NoSim,
+    #[error("connection profile is not a bridge or bond")]
+    NotPortController,
+    #[error("connection profile is not a wired profile")]
+    NotWired,
     #[error("no wifi devices found")]
     NoWiFiDevices,
+    #[error("unsupported VPN configuration file")]
+    UnsupportedVpnConfig,
+    #[error("failed to import VPN configuration: {0}")]
+    VpnImport(String),
+    #[error("invalid WireGuard configuration: {0}")]
+    WireGuardConfig(String),
+    #[error("failed to write WireGuard configuration")]
+    WireGuardExport(std::io::Error),
     #[error("zbus error")]
     Zbus(#[from] zbus::Error),
 }
 
+impl Error {
+    /// NetworkManager's reason for a failed activation, if that is what this error is.
+    pub fn activation(&self) -> Option<ActivationError> {
+        match self {
+            Self::Activation(error) => Some(*error),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum State {
     Ready(zbus::Connection),
@@ -108,6 +173,7 @@ pub async fn watch_connections_changed(
                         req: Request::Reload,
                         state,
                         success: true,
+                        error: None,
                     })
                     .await;
             }
@@ -212,7 +278,11 @@ async fn start_listening(
                         .await
                         .unwrap_or_default()
                     {
-                        if c.id().await.unwrap_or_default().as_str() == ssid.as_ref()
+                        let active_ssid = current_networks::profile_settings(&c)
+                            .await
+                            .as_ref()
+                            .and_then(current_networks::wifi_ssid);
+                        if active_ssid.as_ref() == Some(&ssid)
                             && network_manager.deactivate_connection(&c).await.is_ok()
                         {
                             success = true;
@@ -250,12 +320,19 @@ async fn start_listening(
                         .is_ok();
                     // bluetooth
                     success = success
-                        && Command::new("rfkill")
-                            .arg(if airplane_mode { "block" } else { "unblock" })
-                            .arg("bluetooth")
-                            .output()
-                            .await
-                            .is_ok();
+                        && match rfkill::set_soft_block_all(
+                            rfkill::RFKILL_TYPE_BLUETOOTH,
+                            airplane_mode,
+                        ) {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(
+                                    why = %Error::BluetoothRfkillBlock(why),
+                                    "failed to toggle airplane mode"
+                                );
+                                false
+                            }
+                        };
 
                     let mut state = NetworkManagerState::new(&conn).await.unwrap_or_default();
                     state.airplane_mode = if success {
@@ -271,6 +348,7 @@ async fn start_listening(
                         .send(Event::RequestResponse {
                             req: Request::SetAirplaneMode(airplane_mode),
                             success,
+                            error: None,
                             state,
                         })
                         .await;
@@ -294,38 +372,41 @@ async fn start_listening(
 
                 Some(Request::Authenticate {
                     ssid,
-                    identity,
+                    enterprise,
                     password,
                     hw_address,
                 }) => {
                     let nm_state = NetworkManagerState::new(&conn).await.unwrap_or_default();
-                    let success = nm_state
+                    let result = nm_state
                         .connect_wifi(
                             &conn,
                             &ssid,
-                            identity.as_deref(),
+                            enterprise.as_deref(),
                             Some(password.unsecure()),
                             hw_address,
                         )
-                        .await
-                        .is_ok();
-
-                    _ = output
-                        .send(Event::RequestResponse {
-                            req: Request::Authenticate {
-                                ssid: ssid.clone(),
-                                identity: identity.clone(),
-                                password: password.clone(),
-                                hw_address,
-                            },
-                            success,
-                            state: NetworkManagerState::new(&conn).await.unwrap_or_default(),
-                        })
                         .await;
+
+                    if let Err(why) = &result {
+                        tracing::error!(?why, "failed to authenticate with {ssid}");
+                    }
+
+                    _ = request_result(
+                        &conn,
+                        Request::Authenticate {
+                            ssid,
+                            enterprise,
+                            password,
+                            hw_address,
+                        },
+                        result,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
                 }
 
                 Some(Request::SelectAccessPoint(ssid, hw_address, network_type)) => {
-                    if matches!(network_type, NetworkType::Open) {
+                    if !network_type.requires_secret() {
                         attempt_wifi_connection(&conn, ssid, hw_address, network_type, output)
                             .await;
                     } else {
@@ -340,6 +421,460 @@ async fn start_listening(
                     }
                 }
 
+                Some(Request::ConnectHidden {
+                    ssid,
+                    security,
+                    credentials,
+                }) => {
+                    let result = connect_hidden_wifi(&conn, &ssid, security, &credentials).await;
+
+                    if let Err(why) = &result {
+                        tracing::error!(?why, "failed to connect to hidden network");
+                    }
+
+                    _ = request_result(
+                        &conn,
+                        Request::ConnectHidden {
+                            ssid,
+                            security,
+                            credentials,
+                        },
+                        result,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::CheckConnectivity) => {
+                    let success = match network_manager.check_connectivity().await {
+                        Ok(_) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "connectivity check failed");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::CheckConnectivity, success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::ConnectWps(ssid, hw_address)) => {
+                    let conn = conn.clone();
+                    let mut output = output.clone();
+
+                    // The button may not be pressed for minutes, which must not hold up other
+                    // requests.
+                    tokio::task::spawn(async move {
+                        let nm_state = NetworkManagerState::new(&conn).await.unwrap_or_default();
+                        let access_point = nm_state
+                            .wireless_access_points
+                            .iter()
+                            .find(|ap| ap.ssid == ssid && ap.hw_address == hw_address);
+
+                        let result = match access_point {
+                            Some(ap) => {
+                                wps::connect(&conn, &ssid, hw_address, &ap.path, &mut output).await
+                            }
+                            None => Err(Error::AccessPointNotFound),
+                        };
+
+                        if let Err(why) = &result {
+                            tracing::error!(?why, "failed to connect to {ssid} with WPS");
+                        }
+
+                        _ = request_result(&conn, Request::ConnectWps(ssid, hw_address), result)
+                            .then(|event| output.send(event))
+                            .await;
+                    });
+                }
+
+                Some(Request::ActivateVpn { uuid, secrets }) => {
+                    let result = activate_vpn(&conn, &network_manager, &uuid, &secrets).await;
+
+                    if let Err(why) = &result {
+                        tracing::error!(?why, "failed to activate VPN {uuid}");
+                    }
+
+                    _ = request_result(&conn, Request::ActivateVpn { uuid, secrets }, result)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::ImportVpn(path)) => {
+                    let success = match vpn::import(&conn, &path).await {
+                        Ok(uuid) => {
+                            tracing::info!("imported {path:?} as VPN connection {uuid}");
+                            _ = output
+                                .send(Event::Vpn(vpn::VpnEvent::Imported { uuid }))
+                                .await;
+                            true
+                        }
+                        Err(why) => {
+                            tracing::error!(?why, "failed to import VPN configuration");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::ImportVpn(path), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::UpdateProfile(uuid, profile)) => {
+                    let success = match profile::update(&conn, &uuid, &profile).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to update connection profile {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::UpdateProfile(uuid, profile), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetModemEnabled(modem, enable)) => {
+                    let success = match mobile_broadband::set_enabled(&conn, &modem, enable).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to enable modem {modem}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::SetModemEnabled(modem, enable), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetModemApn(modem, apn)) => {
+                    let success = match mobile_broadband::set_apn(&conn, &modem, &apn).await {
+                        Ok(_) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to set APN of modem {modem}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::SetModemApn(modem, apn), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::UnlockModem(modem, pin)) => {
+                    let success =
+                        match mobile_broadband::unlock(&conn, &modem, pin.unsecure()).await {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(?why, "failed to unlock SIM of modem {modem}");
+                                false
+                            }
+                        };
+
+                    _ = request_response(&conn, Request::UnlockModem(modem, pin), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetAutoconnect(uuid, autoconnect)) => {
+                    let success =
+                        match saved_connections::set_autoconnect(&conn, &uuid, autoconnect).await {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(?why, "failed to set autoconnect on {uuid}");
+                                false
+                            }
+                        };
+
+                    _ = request_response(
+                        &conn,
+                        Request::SetAutoconnect(uuid, autoconnect),
+                        success,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::SetAutoconnectPriority(uuid, priority)) => {
+                    let success =
+                        match saved_connections::set_autoconnect_priority(&conn, &uuid, priority)
+                            .await
+                        {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(
+                                    ?why,
+                                    "failed to set autoconnect priority on {uuid}"
+                                );
+                                false
+                            }
+                        };
+
+                    _ = request_response(
+                        &conn,
+                        Request::SetAutoconnectPriority(uuid, priority),
+                        success,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::SetDeviceManaged(path, managed)) => {
+                    let success = match devices::set_managed(&conn, path.clone(), managed).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to set managed on {path}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::SetDeviceManaged(path, managed), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetDeviceAutoconnect(path, autoconnect)) => {
+                    let success =
+                        match devices::set_autoconnect(&conn, path.clone(), autoconnect).await {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(?why, "failed to set autoconnect on {path}");
+                                false
+                            }
+                        };
+
+                    _ = request_response(
+                        &conn,
+                        Request::SetDeviceAutoconnect(path, autoconnect),
+                        success,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::DisconnectDevice(path)) => {
+                    let success = match devices::disconnect(&conn, path.clone()).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to disconnect {path}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::DisconnectDevice(path), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetMetered(uuid, metered)) => {
+                    let success = match metered::set(&conn, &uuid, metered).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to set metered on {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::SetMetered(uuid, metered), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetProxy(uuid, config)) => {
+                    let success = match proxy::set(&conn, &uuid, &config).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to set proxy on {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::SetProxy(uuid, config), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::SetWired8021x {
+                    uuid,
+                    enterprise,
+                    password,
+                }) => {
+                    let result = wired::set_8021x(
+                        &conn,
+                        &uuid,
+                        enterprise.as_deref(),
+                        password.as_ref().map(SecureString::unsecure),
+                    )
+                    .await;
+
+                    if let Err(why) = &result {
+                        tracing::error!(?why, "failed to set 802.1X authentication on {uuid}");
+                    }
+
+                    _ = request_result(
+                        &conn,
+                        Request::SetWired8021x {
+                            uuid,
+                            enterprise,
+                            password,
+                        },
+                        result,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::StartHotspot {
+                    ssid,
+                    password,
+                    band,
+                    channel,
+                }) => {
+                    let result =
+                        hotspot::start(&conn, &ssid, password.unsecure(), band, channel).await;
+
+                    if let Err(why) = &result {
+                        tracing::error!(?why, "failed to start hotspot");
+                    }
+
+                    _ = request_result(
+                        &conn,
+                        Request::StartHotspot {
+                            ssid,
+                            password,
+                            band,
+                            channel,
+                        },
+                        result,
+                    )
+                    .then(|event| output.send(event))
+                    .await;
+                }
+
+                Some(Request::StopHotspot) => {
+                    let success = match hotspot::stop(&conn).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to stop hotspot");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::StopHotspot, success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::CreateVirtualInterface(config)) => {
+                    let success = match virtual_interface::create(&conn, &config).await {
+                        Ok(uuid) => {
+                            tracing::info!(
+                                "created {} connection {uuid}",
+                                config.kind.connection_type()
+                            );
+                            true
+                        }
+                        Err(why) => {
+                            tracing::error!(?why, "failed to create virtual interface");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::CreateVirtualInterface(config), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::DeleteVirtualInterface(uuid)) => {
+                    let success = match virtual_interface::delete(&conn, &uuid).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to delete virtual interface {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::DeleteVirtualInterface(uuid), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::AttachPort { controller, port }) => {
+                    let success =
+                        match virtual_interface::attach_port(&conn, &controller, &port).await {
+                            Ok(()) => true,
+                            Err(why) => {
+                                tracing::error!(?why, "failed to attach {port} to {controller}");
+                                false
+                            }
+                        };
+
+                    _ = request_response(&conn, Request::AttachPort { controller, port }, success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::DetachPort(port)) => {
+                    let success = match virtual_interface::detach_port(&conn, &port).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to detach port {port}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::DetachPort(port), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::CreateWireGuard(config)) => {
+                    let success = match wireguard::create(&conn, &config).await {
+                        Ok(uuid) => {
+                            tracing::info!("created WireGuard connection {uuid}");
+                            true
+                        }
+                        Err(why) => {
+                            tracing::error!(?why, "failed to create WireGuard connection");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::CreateWireGuard(config), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::UpdateWireGuard(uuid, config)) => {
+                    let success = match wireguard::update(&conn, &uuid, &config).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to update WireGuard connection {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::UpdateWireGuard(uuid, config), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
+                Some(Request::ExportWireGuard(uuid, path)) => {
+                    let success = match wireguard::export(&conn, &uuid, &path).await {
+                        Ok(()) => true,
+                        Err(why) => {
+                            tracing::error!(?why, "failed to export WireGuard connection {uuid}");
+                            false
+                        }
+                    };
+
+                    _ = request_response(&conn, Request::ExportWireGuard(uuid, path), success)
+                        .then(|event| output.send(event))
+                        .await;
+                }
+
                 Some(Request::Activate(device_path, connection_path)) => {
                     let mut success = true;
 
@@ -376,8 +911,9 @@ async fn start_listening(
                             tracing::error!(?why, "error getting network manager settings");
                             _ = output
                                 .send(Event::RequestResponse {
-                                    req: Request::Forget(uuid.clone()),
+                                    req: Request::Remove(uuid.clone()),
                                     success: false,
+                                    error: None,
                                     state: NetworkManagerState::new(&conn)
                                         .await
                                         .unwrap_or_default(),
@@ -419,6 +955,7 @@ async fn start_listening(
                                 .send(Event::RequestResponse {
                                     req: Request::Forget(ssid.clone()),
                                     success: false,
+                                    error: None,
                                     state: NetworkManagerState::new(&conn)
                                         .await
                                         .unwrap_or_default(),
@@ -437,8 +974,7 @@ async fn start_listening(
                         if s.wifi
                             .clone()
                             .and_then(|w| w.ssid)
-                            .and_then(|ssid| String::from_utf8(ssid).ok())
-                            .is_some_and(|s| s == ssid.as_ref())
+                            .is_some_and(|s| ssid == *s.as_slice())
                         {
                             _ = c.delete().await;
                             success = true;
@@ -462,15 +998,177 @@ async fn start_listening(
     }
 }
 
+/// Whether any bluetooth device is soft blocked, which COSMIC treats as airplane mode.
+fn bluetooth_soft_blocked() -> Result<bool, Error> {
+    let devices = rfkill::rfkill_state().map_err(Error::BluetoothRfkillList)?;
+
+    Ok(devices
+        .values()
+        .any(|device| device.type_ == rfkill::RFKILL_TYPE_BLUETOOTH && device.soft))
+}
+
 async fn request_response(conn: &zbus::Connection, req: Request, success: bool) -> Event {
     Event::RequestResponse {
         req,
         success,
+        error: None,
         state: NetworkManagerState::new(conn).await.unwrap_or_default(),
     }
 }
 
-async fn has_saved_wifi_credentials(conn: &zbus::Connection, ssid: &str) -> bool {
+/// Responds to a request which activates a connection, explaining why it failed.
+async fn request_result(conn: &zbus::Connection, req: Request, result: Result<(), Error>) -> Event {
+    Event::RequestResponse {
+        req,
+        success: result.is_ok(),
+        error: result.err().as_ref().and_then(Error::activation),
+        state: NetworkManagerState::new(conn).await.unwrap_or_default(),
+    }
+}
+
+/// Finds a saved connection profile by its UUID.
+async fn find_connection(
+    conn: &zbus::Connection,
+    uuid: &str,
+) -> Result<Connection<'static>, Error> {
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    for connection in nm_settings.list_connections().await? {
+        let Ok(settings) = connection.get_settings().await else {
+            continue;
+        };
+
+        let matches = settings
+            .get("connection")
+            .and_then(|conn| conn.get("uuid"))
+            .and_then(|c_uuid| c_uuid.downcast_ref::<String>().ok())
+            .is_some_and(|c_uuid| c_uuid == uuid);
+
+        if matches {
+            return Ok(connection);
+        }
+    }
+
+    Err(Error::ConnectionNotFound)
+}
+
+/// Reads the settings of the connection profile at `path`.
+async fn connection_settings(
+    conn: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<ConnectionSettings> {
+    let connection = zbus::Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        path,
+        "org.freedesktop.NetworkManager.Settings.Connection",
+    )
+    .await?;
+
+    connection.call("GetSettings", &()).await
+}
+
+/// Borrows a connection's settings in the form accepted by `Update`.
+fn settings_for_update(settings: &ConnectionSettings) -> HashMap<&str, HashMap<&str, Value<'_>>> {
+    settings
+        .iter()
+        .map(|(name, setting)| {
+            let setting = setting
+                .iter()
+                .filter_map(|(key, value)| Some((key.as_str(), Value::try_from(value).ok()?)))
+                .collect();
+            (name.as_str(), setting)
+        })
+        .collect()
+}
+
+/// Reapplies a profile as saved to every device it is active on, so that changes take effect
+/// without reconnecting.
+async fn reapply(conn: &zbus::Connection, uuid: &str) -> Result<(), Error> {
+    let nm = NetworkManager::new(conn).await?;
+    for active in nm.active_connections().await.unwrap_or_default() {
+        if active.uuid().await.ok().as_deref() != Some(uuid) {
+            continue;
+        }
+
+        for device in active.devices().await.unwrap_or_default() {
+            // Empty settings reapply the profile as saved.
+            let reapply = device
+                .inner()
+                .call::<_, _, ()>(
+                    "Reapply",
+                    &(HashMap::<&str, HashMap<&str, Value<'_>>>::new(), 0u64, 0u32),
+                )
+                .await;
+
+            if let Err(why) = reapply {
+                tracing::warn!(?why, "failed to reapply connection {uuid}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a typed value from a setting of a connection profile.
+fn setting_value<T: TryFrom<OwnedValue>>(
+    setting: Option<&HashMap<String, OwnedValue>>,
+    key: &str,
+) -> Option<T> {
+    setting?.get(key)?.try_clone().ok()?.try_into().ok()
+}
+
+async fn activate_vpn(
+    conn: &zbus::Connection,
+    network_manager: &NetworkManager<'_>,
+    uuid: &str,
+    secrets: &HashMap<String, SecureString>,
+) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+
+    if secrets.is_empty() {
+        network_manager
+            .activate_connection_by_paths(
+                connection.inner().path(),
+                &ObjectPath::from_static_str_unchecked("/"),
+            )
+            .await?;
+
+        return Ok(());
+    }
+
+    let uuid = UUID::from(uuid);
+    secret_agent::provide_vpn_secrets(uuid.clone(), secrets.clone());
+
+    let active_conn = match network_manager
+        .activate_connection_by_paths(
+            connection.inner().path(),
+            &ObjectPath::from_static_str_unchecked("/"),
+        )
+        .await
+    {
+        Ok(active_conn) => active_conn,
+        Err(why) => {
+            secret_agent::withdraw_vpn_secrets(&uuid);
+            return Err(why.into());
+        }
+    };
+
+    // The agent may never be asked for the secrets, as when another agent answers first, so
+    // they must not outlive the activation they were given for.
+    let conn = conn.clone();
+    let path = OwnedObjectPath::from(active_conn.inner().path().to_owned());
+    tokio::task::spawn(async move {
+        if let Ok(active_conn) = active_connection_from_path(&conn, path).await {
+            _ = ActivationWatch::without_device().wait(&active_conn).await;
+        }
+        secret_agent::withdraw_vpn_secrets(&uuid);
+    });
+
+    Ok(())
+}
+
+async fn has_saved_wifi_credentials(conn: &zbus::Connection, ssid: &SSID) -> bool {
     let Ok(nm_settings) = NetworkManagerSettings::new(conn).await else {
         return false;
     };
@@ -480,14 +1178,12 @@ async fn has_saved_wifi_credentials(conn: &zbus::Connection, ssid: &str) -> bool
     for connection in known_conns {
         if let Ok(settings) = connection.get_settings().await {
             let settings = Settings::new(settings);
-            if let Some(saved_ssid) = settings
+            if settings
                 .wifi
                 .and_then(|w| w.ssid)
-                .and_then(|ssid| String::from_utf8(ssid).ok())
+                .is_some_and(|saved_ssid| *ssid == *saved_ssid.as_slice())
             {
-                if saved_ssid == ssid {
-                    return true;
-                }
+                return true;
             }
         }
     }
@@ -504,20 +1200,18 @@ async fn attempt_wifi_connection(
 ) {
     let state = NetworkManagerState::new(conn).await.unwrap_or_default();
 
-    let success = if let Err(err) = state
-        .connect_wifi(conn, ssid.as_ref(), None, None, hw_address)
-        .await
-    {
+    let result = state
+        .connect_wifi(conn, &ssid, None, None, hw_address)
+        .await;
+
+    if let Err(err) = &result {
         tracing::error!("Failed to connect to access point: {:?}", err);
-        false
-    } else {
-        true
-    };
+    }
 
-    _ = request_response(
+    _ = request_result(
         conn,
         Request::SelectAccessPoint(ssid, hw_address, network_type),
-        success,
+        result,
     )
     .then(|event| output.send(event))
     .await;
@@ -527,29 +1221,109 @@ async fn attempt_wifi_connection(
 pub enum Request {
     /// Activate a device's connection profile
     Activate(ObjectPath<'static>, ObjectPath<'static>),
+    /// Activate a VPN profile, supplying any secrets it prompted for.
+    ///
+    /// The secrets are answered by the agent served by [`secret_agent::watch`].
+    ActivateVpn {
+        uuid: UUID,
+        secrets: HashMap<String, SecureString>,
+    },
+    /// Attach a profile as a port of a bridge or bond.
+    AttachPort { controller: UUID, port: UUID },
+    /// Add a bridge, bond or VLAN profile.
+    CreateVirtualInterface(Box<virtual_interface::VirtualInterfaceConfig>),
+    /// Add a WireGuard connection profile.
+    CreateWireGuard(Box<WireGuardConfig>),
     /// Deactivate a connection
     Deactivate(UUID),
+    /// Delete a bridge, bond or VLAN profile, detaching its ports.
+    DeleteVirtualInterface(UUID),
+    /// Detach a port profile from its bridge or bond.
+    DetachPort(UUID),
     /// Disconnect from an access point.
     Disconnect(SSID),
+    /// Deactivate the connection on a device without letting it autoconnect again.
+    DisconnectDevice(ObjectPath<'static>),
+    /// Write a WireGuard profile to a `wg-quick` configuration file.
+    ExportWireGuard(UUID, PathBuf),
     /// Forget a known access point.
     Forget(SSID),
     /// Create a connection to a new access point.
     Authenticate {
-        ssid: String,
-        identity: Option<String>,
+        ssid: SSID,
+        /// 802.1X credentials for WPA-Enterprise networks.
+        enterprise: Option<Box<EnterpriseCredentials>>,
         password: SecureString,
         hw_address: HwAddress,
     },
+    /// Import an OpenVPN `.ovpn` or WireGuard `.conf` file.
+    ImportVpn(PathBuf),
     /// Signal to reload the service.
     Reload,
     /// Remove a connection profile.
     Remove(UUID),
     /// Connect to a known access point.
     SelectAccessPoint(SSID, HwAddress, NetworkType),
+    /// Join a network that does not broadcast its SSID.
+    ConnectHidden {
+        ssid: SSID,
+        security: NetworkType,
+        credentials: Box<Credentials>,
+    },
+    /// Ask NetworkManager to recheck connectivity now.
+    CheckConnectivity,
+    /// Join an access point by pressing its WPS button, reported through [`Event::Wps`].
+    ConnectWps(SSID, HwAddress),
+    /// Set whether NetworkManager may activate a profile on its own.
+    SetAutoconnect(UUID, bool),
+    /// Set the autoconnect priority of a profile; higher priorities are preferred.
+    SetAutoconnectPriority(UUID, i32),
     /// Toggle airplaine mode.
     SetAirplaneMode(bool),
+    /// Set whether NetworkManager may activate connections on a device on its own.
+    SetDeviceAutoconnect(ObjectPath<'static>, bool),
+    /// Set whether NetworkManager controls a device.
+    SetDeviceManaged(ObjectPath<'static>, bool),
+    /// Power a modem's radio on or off.
+    SetModemEnabled(ObjectPath<'static>, bool),
+    /// Set the APN used by a modem's mobile broadband profile.
+    SetModemApn(ObjectPath<'static>, Box<mobile_broadband::Apn>),
+    /// Unlock the SIM card of a modem with its PIN.
+    UnlockModem(ObjectPath<'static>, SecureString),
+    /// Mark a connection profile as metered or not, or leave it for NetworkManager to guess.
+    SetMetered(UUID, Metered),
+    /// Replace the proxy settings of a connection profile.
+    SetProxy(UUID, Box<proxy::ProxyConfig>),
     /// Toggle WiFi enablement.
     SetWiFi(bool),
+    /// Enable 802.1X authentication on a wired profile, or disable it with `None`.
+    SetWired8021x {
+        uuid: UUID,
+        enterprise: Option<Box<EnterpriseCredentials>>,
+        password: Option<SecureString>,
+    },
+    /// Share the network connection through a Wi-Fi access point.
+    StartHotspot {
+        ssid: SSID,
+        password: SecureString,
+        /// Picked by NetworkManager when unset, unless implied by `channel`.
+        band: Option<Band>,
+        channel: Option<u32>,
+    },
+    /// Stop sharing the network connection.
+    StopHotspot,
+    /// Edit the addressing, DNS and link settings of a connection profile.
+    UpdateProfile(UUID, Box<ConnectionProfile>),
+    /// Replace the settings of a WireGuard profile.
+    UpdateWireGuard(UUID, Box<WireGuardConfig>),
+}
+
+/// Secrets supplied when joining a network.
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    pub password: Option<SecureString>,
+    /// 802.1X credentials for WPA-Enterprise networks.
+    pub enterprise: Option<EnterpriseCredentials>,
 }
 
 #[derive(Debug, Clone)]
@@ -558,37 +1332,76 @@ pub enum Event {
         req: Request,
         state: NetworkManagerState,
         success: bool,
+        /// NetworkManager's reason for a failed connection attempt.
+        error: Option<ActivationError>,
     },
     Init {
         conn: zbus::Connection,
         sender: UnboundedSender<Request>,
         state: NetworkManagerState,
     },
-    Devices,
+    /// A device appeared. Sent by [`devices::subscription`].
+    DeviceAdded(devices::DeviceInfo),
+    /// A device is gone.
+    DeviceRemoved(ObjectPath<'static>),
+    /// A field of a device changed.
+    DeviceChanged(ObjectPath<'static>, devices::DeviceUpdate),
     WiFiEnabled(bool),
     WirelessAccessPoints,
+    /// An access point became visible. Sent by [`available_wifi::subscription`].
+    AccessPointAdded(AccessPoint),
+    /// An access point is no longer visible.
+    AccessPointRemoved(ObjectPath<'static>),
+    /// The signal strength of an access point changed.
+    AccessPointStrengthChanged(ObjectPath<'static>, u8),
     ActiveConns,
+    Vpn(vpn::VpnEvent),
+    /// The connectivity state changed. Sent by [`connectivity::subscription`].
+    Connectivity(NmConnectivityState),
+    /// A captive portal was detected.
+    ///
+    /// `check_uri` is NetworkManager's connectivity check URI, not the portal itself. Opening it
+    /// in a browser makes the portal redirect to its sign-in page.
+    CaptivePortal {
+        check_uri: Option<String>,
+    },
+    /// The modems and their status. Sent by [`mobile_broadband::subscription`].
+    Modems(Vec<mobile_broadband::Modem>),
+    /// Whether the primary connection is metered changed. Sent by [`metered::subscription`].
+    Metered(Metered),
+    /// Traffic counters of each device. Sent by [`statistics::subscription`].
+    Statistics(Vec<statistics::DeviceStatistics>),
+    /// A request from the agent served by [`secret_agent::watch`].
+    SecretAgent(secret_agent::Message),
+    /// Progress of a [`Request::ConnectWps`] attempt.
+    Wps(wps::WpsProgress),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NetworkManagerState {
+    /// The strongest access point of each SSID, per wireless device.
     pub wireless_access_points: Vec<AccessPoint>,
+    /// Every access point, with one entry per BSSID.
+    pub all_access_points: Vec<AccessPoint>,
     pub active_conns: Vec<ActiveConnectionInfo>,
     pub known_access_points: Vec<AccessPoint>,
     pub wifi_enabled: bool,
     pub airplane_mode: bool,
     pub connectivity: NmConnectivityState,
+    pub hotspot: Option<hotspot::Hotspot>,
 }
 
 impl Default for NetworkManagerState {
     fn default() -> Self {
         Self {
             wireless_access_points: Vec::new(),
+            all_access_points: Vec::new(),
             active_conns: Vec::new(),
             known_access_points: Vec::new(),
             wifi_enabled: false,
             airplane_mode: false,
             connectivity: NmConnectivityState::Unknown,
+            hotspot: None,
         }
     }
 }
@@ -608,20 +1421,15 @@ impl NetworkManagerState {
         conn: &zbus::Connection,
         network_manager: &NetworkManager<'_>,
     ) -> Result<(), Error> {
-        let (airplane_mode, wireless_enabled, settings_res) = futures::join!(
-            Command::new("rfkill")
-                .arg("list")
-                .arg("bluetooth")
-                .output()
-                .then(|res| async move {
-                    let Ok(output) = res else {
-                        return false;
-                    };
+        let airplane_mode = match bluetooth_soft_blocked() {
+            Ok(blocked) => blocked,
+            Err(why) => {
+                tracing::warn!(why = %why, "failed to read airplane mode");
+                false
+            }
+        };
 
-                    std::str::from_utf8(&output.stdout)
-                        .ok()
-                        .map_or(false, |stdout| stdout.contains("Soft blocked: yes"))
-                }),
+        let (wireless_enabled, settings_res) = futures::join!(
             network_manager
                 .wireless_enabled()
                 .then(|res| async move { res.unwrap_or_default() }),
@@ -657,6 +1465,9 @@ impl NetworkManagerState {
                         ActiveConnectionInfo::Vpn { name, .. } => format!("0{name}"),
                         ActiveConnectionInfo::Wired { name, .. } => format!("1{name}"),
                         ActiveConnectionInfo::WiFi { name, .. } => format!("2{name}"),
+                        ActiveConnectionInfo::MobileBroadband { name, .. } => {
+                            format!("3{name}")
+                        }
                     };
                     helper(a).cmp(&helper(b))
                 });
@@ -693,34 +1504,33 @@ impl NetworkManagerState {
         );
 
         // Concurrently get
-        let known_ssid: Vec<Arc<str>> = futures::stream::FuturesOrdered::from_iter(
+        let known_ssid: Vec<SSID> = futures::stream::FuturesOrdered::from_iter(
             known_conns.into_iter().map(|c| async move {
                 let s = c.get_settings().await.ok()?;
                 let s = Settings::new(s);
-                let curr_ssid = s
-                    .wifi
-                    .clone()
-                    .and_then(|w| w.ssid)
-                    .and_then(|ssid| String::from_utf8(ssid).ok())?;
-
-                Some(Arc::from(curr_ssid))
+                s.wifi.clone().and_then(|w| w.ssid).map(SSID::from)
             }),
         )
         .filter_map(|c| async move { c })
         .collect()
         .await;
 
+        let all_access_points = wireless_access_points;
+        let wireless_access_points = available_wifi::strongest_per_ssid(&all_access_points);
+
         self.known_access_points = wireless_access_points
             .iter()
             .filter(|a| {
                 known_ssid.contains(&a.ssid)
-                    && !active_conns.iter().any(|ac| &ac.name() == a.ssid.as_ref())
+                    && !active_conns.iter().any(|ac| ac.ssid() == Some(&a.ssid))
             })
             .cloned()
             .collect();
 
+        self.all_access_points = all_access_points;
         self.wireless_access_points = wireless_access_points;
         self.active_conns = active_conns;
+        self.hotspot = hotspot::state(conn, network_manager).await;
         self.connectivity = connectivity?;
 
         Ok(())
@@ -731,23 +1541,31 @@ impl NetworkManagerState {
         self.active_conns = Vec::new();
         self.known_access_points = Vec::new();
         self.wireless_access_points = Vec::new();
+        self.all_access_points = Vec::new();
     }
 
     async fn connect_wifi<'a>(
         &self,
         conn: &zbus::Connection,
-        ssid: &str,
-        identity: Option<&str>,
+        ssid: &SSID,
+        enterprise: Option<&EnterpriseCredentials>,
         password: Option<&str>,
         hw_address: HwAddress,
     ) -> Result<(), Error> {
         let nm = NetworkManager::new(conn).await?;
 
         for c in nm.active_connections().await.unwrap_or_default() {
+            let Some(active_ssid) = current_networks::profile_settings(&c)
+                .await
+                .as_ref()
+                .and_then(current_networks::wifi_ssid)
+            else {
+                continue;
+            };
             if self
                 .wireless_access_points
                 .iter()
-                .any(|w| Ok(Some(w.ssid.as_ref())) == c.cached_id().as_ref().map(|v| v.as_deref()))
+                .any(|w| w.ssid == active_ssid)
             {
                 _ = nm.deactivate_connection(&c).await;
                 break;
@@ -757,52 +1575,12 @@ impl NetworkManagerState {
         let Some(ap) = self
             .wireless_access_points
             .iter()
-            .find(|ap| ap.ssid.as_ref() == ssid && ap.hw_address == hw_address)
+            .find(|ap| ap.ssid == *ssid && ap.hw_address == hw_address)
         else {
             return Err(Error::AccessPointNotFound);
         };
 
-        let mut conn_settings: HashMap<&str, HashMap<&str, zvariant::Value>> = HashMap::from([
-            (
-                "802-11-wireless",
-                HashMap::from([("ssid", Value::Array(ssid.as_bytes().into()))]),
-            ),
-            (
-                "connection",
-                HashMap::from([
-                    ("id", Value::Str(ssid.into())),
-                    ("type", Value::Str("802-11-wireless".into())),
-                ]),
-            ),
-        ]);
-        if let Some(identity) = identity {
-            conn_settings.insert(
-                "802-1x",
-                HashMap::from([
-                    ("identity", Value::Str(identity.into())),
-                    // most common default
-                    ("eap", Value::Array(vec!["peap"].into())),
-                    // most common default
-                    ("phase2-auth", Value::Str("mschapv2".into())),
-                    ("password", Value::Str(password.unwrap_or("").into())),
-                ]),
-            );
-            let wireless = conn_settings.get_mut("802-11-wireless").unwrap();
-            wireless.insert("security", Value::Str("802-11-wireless-security".into()));
-            wireless.insert("mode", Value::Str("infrastructure".into()));
-            conn_settings.insert(
-                "802-11-wireless-security",
-                HashMap::from([("key-mgmt", Value::Str("wpa-eap".into()))]),
-            );
-        } else if let Some(pass) = password {
-            conn_settings.insert(
-                "802-11-wireless-security",
-                HashMap::from([
-                    ("psk", Value::Str(pass.into())),
-                    ("key-mgmt", Value::Str("wpa-psk".into())),
-                ]),
-            );
-        }
+        let conn_settings = wifi_settings(ssid, ap.network_type, enterprise, password)?;
 
         let devices = nm.devices().await?;
         for device in devices {
@@ -820,19 +1598,18 @@ impl NetworkManagerState {
                 let settings = c.get_settings().await.ok().unwrap_or_default();
 
                 let s = Settings::new(settings);
-                if let Some(cur_ssid) = s
-                    .wifi
+                if s.wifi
                     .clone()
                     .and_then(|w| w.ssid)
-                    .and_then(|ssid| String::from_utf8(ssid).ok())
+                    .is_some_and(|cur_ssid| *ssid == *cur_ssid.as_slice())
                 {
-                    if cur_ssid == ssid {
-                        known_conn = Some(c);
-                        break;
-                    }
+                    known_conn = Some(c);
+                    break;
                 }
             }
 
+            let watch = ActivationWatch::new(&device).await?;
+
             let active_conn = if let Some(known_conn) = known_conn.as_ref() {
                 // update settings if needed
                 if password.is_some() {
@@ -844,46 +1621,156 @@ impl NetworkManagerState {
                 let (_, active_conn) = nm
                     .add_and_activate_connection(conn_settings, device.inner().path(), &ap.path)
                     .await?;
-                let dummy = ActiveConnectionProxy::new(&conn, active_conn).await?;
-                let active = ActiveConnectionProxy::builder(&conn)
-                    .destination(dummy.inner().destination().to_owned())
-                    .unwrap()
-                    .interface(dummy.inner().interface().to_owned())
-                    .unwrap()
-                    .path(dummy.inner().path().to_owned())
-                    .unwrap()
-                    .build()
-                    .await
-                    .unwrap();
-                ActiveConnection::from(active)
+                active_connection_from_path(conn, active_conn).await?
             };
-            let mut changes = active_conn.receive_state_changed().await;
-            _ = tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-            let mut count = 5;
-            loop {
-                let state = active_conn.state().await;
-                if let Ok(enums::ActiveConnectionState::Activated) = state {
-                    return Ok(());
-                } else if let Ok(enums::ActiveConnectionState::Deactivated) = state {
-                    return Err(Error::ConnectionActivate);
-                }
-                match tokio::time::timeout(Duration::from_secs(20), changes.next()).await {
-                    Ok(Some(s)) => {
-                        let state = s.get().await.unwrap_or_default().into();
-                        if matches!(state, enums::ActiveConnectionState::Activated) {
-                            return Ok(());
-                        }
-                    }
-                    _ => {}
-                };
 
-                count -= 1;
-                if count <= 0 {
-                    return Err(Error::ConnectionActivate);
-                }
-            }
+            return watch.wait(&active_conn).await;
         }
 
         Err(Error::NoWiFiDevices)
     }
 }
+
+/// Creates and activates a profile for a network that does not broadcast its SSID.
+async fn connect_hidden_wifi(
+    conn: &zbus::Connection,
+    ssid: &SSID,
+    security: NetworkType,
+    credentials: &Credentials,
+) -> Result<(), Error> {
+    let nm = NetworkManager::new(conn).await?;
+
+    let mut wifi_device = None;
+    for device in nm.devices().await? {
+        if let Ok(DeviceType::Wifi) = device.device_type().await {
+            wifi_device = Some(device);
+            break;
+        }
+    }
+
+    let Some(device) = wifi_device else {
+        return Err(Error::NoWiFiDevices);
+    };
+
+    let mut conn_settings = wifi_settings(
+        ssid,
+        security,
+        credentials.enterprise.as_ref(),
+        credentials.password.as_ref().map(SecureString::unsecure),
+    )?;
+
+    if let Some(wireless) = conn_settings.get_mut("802-11-wireless") {
+        wireless.insert("hidden", Value::Bool(true));
+    }
+
+    let watch = ActivationWatch::new(&device).await?;
+
+    // Without an access point object, NetworkManager probes for the SSID itself.
+    let (_, active_conn) = nm
+        .add_and_activate_connection(
+            conn_settings,
+            device.inner().path(),
+            &ObjectPath::from_static_str_unchecked("/"),
+        )
+        .await?;
+
+    let active_conn = active_connection_from_path(conn, active_conn).await?;
+    watch.wait(&active_conn).await
+}
+
+/// Builds the settings of a new Wi-Fi connection profile.
+///
+/// Fails with [`Error::EnterpriseCredentialsRequired`] for a WPA-Enterprise network without
+/// `enterprise` credentials.
+fn wifi_settings<'a>(
+    ssid: &'a SSID,
+    network_type: NetworkType,
+    enterprise: Option<&'a EnterpriseCredentials>,
+    password: Option<&'a str>,
+) -> Result<HashMap<&'static str, HashMap<&'static str, Value<'a>>>, Error> {
+    let mut conn_settings: HashMap<&str, HashMap<&str, zvariant::Value>> = HashMap::from([
+        (
+            "802-11-wireless",
+            HashMap::from([("ssid", Value::Array(ssid.as_bytes().into()))]),
+        ),
+        (
+            "connection",
+            HashMap::from([
+                ("id", Value::from(ssid.to_string())),
+                ("type", Value::Str("802-11-wireless".into())),
+            ]),
+        ),
+    ]);
+
+    let key_mgmt = match enterprise {
+        Some(enterprise) => {
+            conn_settings.insert("802-1x", enterprise.settings(password));
+            let wireless = conn_settings.get_mut("802-11-wireless").unwrap();
+            wireless.insert("security", Value::Str("802-11-wireless-security".into()));
+            wireless.insert("mode", Value::Str("infrastructure".into()));
+            NetworkType::EAP.key_mgmt()
+        }
+        None if network_type == NetworkType::EAP => {
+            return Err(Error::EnterpriseCredentialsRequired);
+        }
+        None => network_type.key_mgmt(),
+    };
+
+    if let Some(key_mgmt) = key_mgmt {
+        let mut security = HashMap::from([("key-mgmt", Value::Str(key_mgmt.into()))]);
+
+        // Without a password, NetworkManager asks a secret agent for it.
+        match (enterprise, network_type, password) {
+            (
+                None,
+                NetworkType::PSK | NetworkType::Sae | NetworkType::SaeTransition,
+                Some(pass),
+            ) => {
+                security.insert("psk", Value::Str(pass.into()));
+            }
+            _ => (),
+        }
+
+        conn_settings.insert("802-11-wireless-security", security);
+    }
+
+    Ok(conn_settings)
+}
+
+async fn active_connection_from_path(
+    conn: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<ActiveConnection<'static>> {
+    let dummy = ActiveConnectionProxy::new(conn, path).await?;
+    let active = ActiveConnectionProxy::builder(conn)
+        .destination(dummy.inner().destination().to_owned())?
+        .interface(dummy.inner().interface().to_owned())?
+        .path(dummy.inner().path().to_owned())?
+        .build()
+        .await?;
+    Ok(ActiveConnection::from(active))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wifi_settings_security() {
+        let ssid = SSID::from("Example");
+
+        let settings = wifi_settings(&ssid, NetworkType::Sae, None, Some("hunter22")).unwrap();
+        let security = &settings["802-11-wireless-security"];
+        assert_eq!(security["key-mgmt"], Value::from("sae"));
+        assert_eq!(security["psk"], Value::from("hunter22"));
+
+        // Enterprise networks take their secrets from the 802.1X setting instead.
+        assert!(matches!(
+            wifi_settings(&ssid, NetworkType::EAP, None, Some("hunter22")),
+            Err(Error::EnterpriseCredentialsRequired)
+        ));
+
+        let settings = wifi_settings(&ssid, NetworkType::Open, None, Some("hunter22")).unwrap();
+        assert!(!settings.contains_key("802-11-wireless-security"));
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ConnectionSettings, Error, find_connection, ip_network::IpNetwork, setting_value,
+    settings_for_update,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use zbus::zvariant::{OwnedValue, Value};
+
+/// Keys of an `ipv4` or `ipv6` setting which are written by [`IpConfig`].
+pub(super) const IP_KEYS: &[&str] = &[
+    "method",
+    "addresses",
+    "address-data",
+    "gateway",
+    "routes",
+    "route-data",
+    "dns",
+    "dns-search",
+    "ignore-auto-dns",
+];
+
+/// Settings of the link type which hold the MTU and MAC address.
+const LINK_SETTINGS: &[&str] = &["802-3-ethernet", "802-11-wireless"];
+
+/// The editable parts of a wired or Wi-Fi connection profile.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConnectionProfile {
+    /// Name of the profile.
+    pub id: String,
+    pub ipv4: IpConfig,
+    pub ipv6: IpConfig,
+    /// Uses the device's default when unset.
+    pub mtu: Option<u32>,
+    /// A MAC address, or one of `preserve`, `permanent`, `random` and `stable`.
+    pub cloned_mac_address: Option<String>,
+    pub metered: Metered,
+    /// Profiles with a higher priority are preferred when autoconnecting.
+    pub autoconnect_priority: i32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IpConfig {
+    pub method: IpMethod,
+    pub addresses: Vec<IpNetwork>,
+    pub gateway: Option<IpAddr>,
+    pub routes: Vec<Route>,
+    pub dns: Vec<IpAddr>,
+    pub dns_search: Vec<String>,
+    /// Use only the configured nameservers, ignoring those from DHCP or router advertisements.
+    pub ignore_auto_dns: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IpMethod {
+    #[default]
+    Auto,
+    Manual,
+    LinkLocal,
+    /// Shares the connection with other devices on the link.
+    Shared,
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub destination: IpNetwork,
+    pub next_hop: Option<IpAddr>,
+    pub metric: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Metered {
+    #[default]
+    Unknown,
+    Yes,
+    No,
+    /// Guessed by NetworkManager; only reported for devices.
+    GuessYes,
+    /// Guessed by NetworkManager; only reported for devices.
+    GuessNo,
+}
+
+impl From<u32> for Metered {
+    fn from(metered: u32) -> Self {
+        match metered {
+            1 => Self::Yes,
+            2 => Self::No,
+            3 => Self::GuessYes,
+            4 => Self::GuessNo,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Metered {
+    /// Value of the `connection.metered` setting, where guesses are left to NetworkManager.
+    pub fn setting(self) -> i32 {
+        match self {
+            Self::Yes => 1,
+            Self::No => 2,
+            _ => 0,
+        }
+    }
+}
+
+impl IpMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Manual => "manual",
+            Self::LinkLocal => "link-local",
+            Self::Shared => "shared",
+            Self::Disabled => "disabled",
+        }
+    }
+
+    fn from_setting(method: &str) -> Self {
+        match method {
+            "manual" => Self::Manual,
+            "link-local" => Self::LinkLocal,
+            "shared" => Self::Shared,
+            "disabled" | "ignore" => Self::Disabled,
+            _ => Self::Auto,
+        }
+    }
+}
+
+impl ConnectionProfile {
+    pub fn from_settings(settings: &ConnectionSettings) -> Self {
+        let connection = settings.get("connection");
+        let link = setting_value::<String>(connection, "type")
+            .filter(|kind| LINK_SETTINGS.contains(&kind.as_str()))
+            .and_then(|kind| settings.get(kind.as_str()));
+
+        let cloned_mac_address =
+            setting_value::<String>(link, "assigned-mac-address").or_else(|| {
+                setting_value::<Vec<u8>>(link, "cloned-mac-address")
+                    .filter(|mac| !mac.is_empty())
+                    .map(|mac| itertools::join(mac.iter().map(|byte| format!("{byte:02X}")), ":"))
+            });
+
+        Self {
+            id: setting_value(connection, "id").unwrap_or_default(),
+            ipv4: IpConfig::from_setting(settings.get("ipv4"), false),
+            ipv6: IpConfig::from_setting(settings.get("ipv6"), true),
+            mtu: setting_value::<u32>(link, "mtu").filter(|&mtu| mtu != 0),
+            cloned_mac_address,
+            metered: setting_value::<i32>(connection, "metered")
+                .and_then(|metered| u32::try_from(metered).ok())
+                .map(Metered::from)
+                .unwrap_or_default(),
+            autoconnect_priority: setting_value(connection, "autoconnect-priority")
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl IpConfig {
+    /// Builds an `ipv4` or `ipv6` setting. Entries of the other address family are skipped.
+    pub fn settings(&self, ipv6: bool) -> HashMap<&'static str, Value<'static>> {
+        let family = |address: &IpAddr| address.is_ipv6() == ipv6;
+
+        let addresses: Vec<HashMap<&str, Value>> = self
+            .addresses
+            .iter()
+            .filter(|network| family(&network.address))
+            .map(|network| {
+                HashMap::from([
+                    ("address", Value::from(network.address.to_string())),
+                    ("prefix", Value::U32(network.prefix.into())),
+                ])
+            })
+            .collect();
+
+        let routes: Vec<HashMap<&str, Value>> = self
+            .routes
+            .iter()
+            .filter(|route| family(&route.destination.address))
+            .map(Route::settings)
+            .collect();
+
+        // NetworkManager stores IPv4 nameservers in network byte order.
+        let dns = if ipv6 {
+            let dns: Vec<Vec<u8>> = self
+                .dns
+                .iter()
+                .filter_map(|address| match address {
+                    IpAddr::V6(address) => Some(address.octets().to_vec()),
+                    IpAddr::V4(_) => None,
+                })
+                .collect();
+            Value::from(dns)
+        } else {
+            let dns: Vec<u32> = self
+                .dns
+                .iter()
+                .filter_map(|address| match address {
+                    IpAddr::V4(address) => Some(u32::from_ne_bytes(address.octets())),
+                    IpAddr::V6(_) => None,
+                })
+                .collect();
+            Value::from(dns)
+        };
+
+        let mut settings = HashMap::from([
+            ("method", Value::from(self.method.as_str())),
+            ("address-data", Value::from(addresses)),
+            ("route-data", Value::from(routes)),
+            ("dns", dns),
+            ("dns-search", Value::from(self.dns_search.clone())),
+            ("ignore-auto-dns", Value::Bool(self.ignore_auto_dns)),
+        ]);
+
+        if let Some(gateway) = self.gateway.filter(family) {
+            settings.insert("gateway", Value::from(gateway.to_string()));
+        }
+
+        settings
+    }
+
+    /// Reads an `ipv4` or `ipv6` setting.
+    pub fn from_setting(setting: Option<&HashMap<String, OwnedValue>>, ipv6: bool) -> Self {
+        let addresses = setting_value::<Vec<HashMap<String, OwnedValue>>>(setting, "address-data")
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|data| {
+                Some(IpNetwork {
+                    address: setting_value::<String>(Some(data), "address")?
+                        .parse()
+                        .ok()?,
+                    prefix: u8::try_from(setting_value::<u32>(Some(data), "prefix")?).ok()?,
+                })
+            })
+            .collect();
+
+        let routes = setting_value::<Vec<HashMap<String, OwnedValue>>>(setting, "route-data")
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Route::from_setting)
+            .collect();
+
+        let dns = if ipv6 {
+            setting_value::<Vec<Vec<u8>>>(setting, "dns")
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|address| {
+                    <[u8; 16]>::try_from(address)
+                        .ok()
+                        .map(|octets| IpAddr::V6(Ipv6Addr::from(octets)))
+                })
+                .collect()
+        } else {
+            setting_value::<Vec<u32>>(setting, "dns")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|address| IpAddr::V4(Ipv4Addr::from(address.to_ne_bytes())))
+                .collect()
+        };
+
+        Self {
+            method: setting_value::<String>(setting, "method")
+                .map_or(IpMethod::Auto, |method| IpMethod::from_setting(&method)),
+            addresses,
+            gateway: setting_value::<String>(setting, "gateway")
+                .and_then(|gateway| gateway.parse().ok()),
+            routes,
+            dns,
+            dns_search: setting_value(setting, "dns-search").unwrap_or_default(),
+            ignore_auto_dns: setting_value(setting, "ignore-auto-dns").unwrap_or_default(),
+        }
+    }
+}
+
+impl Route {
+    fn settings(&self) -> HashMap<&'static str, Value<'static>> {
+        let mut settings = HashMap::from([
+            ("dest", Value::from(self.destination.address.to_string())),
+            ("prefix", Value::U32(self.destination.prefix.into())),
+        ]);
+
+        if let Some(next_hop) = self.next_hop {
+            settings.insert("next-hop", Value::from(next_hop.to_string()));
+        }
+
+        if let Some(metric) = self.metric {
+            settings.insert("metric", Value::U32(metric));
+        }
+
+        settings
+    }
+
+    fn from_setting(setting: &HashMap<String, OwnedValue>) -> Option<Self> {
+        let setting = Some(setting);
+
+        Some(Self {
+            destination: IpNetwork {
+                address: setting_value::<String>(setting, "dest")?.parse().ok()?,
+                prefix: u8::try_from(setting_value::<u32>(setting, "prefix")?).ok()?,
+            },
+            next_hop: setting_value::<String>(setting, "next-hop")
+                .and_then(|next_hop| next_hop.parse().ok()),
+            metric: setting_value(setting, "metric"),
+        })
+    }
+}
+
+/// Reads the editable settings of a connection profile.
+pub async fn load(conn: &zbus::Connection, uuid: &str) -> Result<ConnectionProfile, Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    Ok(ConnectionProfile::from_settings(&settings))
+}
+
+/// Writes the editable settings of a connection profile, keeping all others as they are.
+pub async fn update(
+    conn: &zbus::Connection,
+    uuid: &str,
+    profile: &ConnectionProfile,
+) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let kind = setting_value::<String>(settings.get("connection"), "type");
+    let mut update = settings_for_update(&settings);
+
+    let connection_setting = update.entry("connection").or_default();
+    connection_setting.insert("id", Value::from(profile.id.as_str()));
+    connection_setting.insert("metered", Value::I32(profile.metered.setting()));
+    connection_setting.insert(
+        "autoconnect-priority",
+        Value::I32(profile.autoconnect_priority),
+    );
+
+    for (family, config, ipv6) in [
+        ("ipv4", &profile.ipv4, false),
+        ("ipv6", &profile.ipv6, true),
+    ] {
+        let setting = update.entry(family).or_default();
+        setting.retain(|key, _| !IP_KEYS.contains(key));
+        setting.extend(config.settings(ipv6));
+    }
+
+    if let Some(link) = kind.as_deref().filter(|kind| LINK_SETTINGS.contains(kind)) {
+        let setting = update.entry(link).or_default();
+        setting.retain(|key, _| {
+            !matches!(*key, "mtu" | "cloned-mac-address" | "assigned-mac-address")
+        });
+
+        if let Some(mtu) = profile.mtu {
+            setting.insert("mtu", Value::U32(mtu));
+        }
+
+        if let Some(mac) = profile.cloned_mac_address.as_deref() {
+            setting.insert("assigned-mac-address", Value::from(mac));
+        }
+    }
+
+    connection.update(update).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_config_settings_round_trip() {
+        let config = IpConfig {
+            method: IpMethod::Manual,
+            addresses: vec![
+                "192.168.1.10/24".parse().unwrap(),
+                "fd00::10/64".parse().unwrap(),
+            ],
+            gateway: Some(IpAddr::from([192, 168, 1, 1])),
+            routes: vec![Route {
+                destination: "10.0.0.0/8".parse().unwrap(),
+                next_hop: Some(IpAddr::from([192, 168, 1, 254])),
+                metric: Some(100),
+            }],
+            dns: vec![IpAddr::from([1, 1, 1, 1])],
+            dns_search: vec!["example.com".to_owned()],
+            ignore_auto_dns: true,
+        };
+
+        let setting: HashMap<String, OwnedValue> = config
+            .settings(false)
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value.try_to_owned().unwrap()))
+            .collect();
+
+        let read = IpConfig::from_setting(Some(&setting), false);
+        assert_eq!(
+            IpConfig {
+                addresses: config.addresses[..1].to_vec(),
+                ..config
+            },
+            read
+        );
+    }
+}
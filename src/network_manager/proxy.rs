@@ -0,0 +1,301 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ConnectionSettings, Error, find_connection, reapply, setting_value, settings_for_update,
+};
+use std::{collections::HashMap, fmt::Write as _};
+use zbus::zvariant::Value;
+
+// `NMSettingProxyMethod`
+const METHOD_NONE: i32 = 0;
+const METHOD_AUTO: i32 = 1;
+
+/// Marks a PAC script as written by [`ManualProxy::pac_script`], so it can be read back.
+const PAC_HEADER: &str = "// Manual proxy configuration generated by COSMIC Settings";
+
+/// The proxy settings of a connection profile.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// Connect directly.
+    #[default]
+    None,
+    /// Use a PAC file, or discover one with WPAD when `pac_url` is unset.
+    Auto { pac_url: Option<String> },
+    /// Use fixed proxy servers.
+    ///
+    /// NetworkManager only knows of PAC files, so these are saved as a generated PAC script.
+    Manual(ManualProxy),
+}
+
+/// Proxy servers given as `host:port`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ManualProxy {
+    pub http: Option<String>,
+    pub https: Option<String>,
+    /// Used for any traffic which the other proxies do not handle.
+    pub socks: Option<String>,
+    /// Host patterns, such as `*.local`, which are connected to directly.
+    pub ignore_hosts: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reads the `proxy` setting of a connection profile.
+    ///
+    /// A PAC script which was not generated by [`ManualProxy::pac_script`] is reported as
+    /// [`ProxyConfig::Auto`].
+    pub fn from_settings(settings: &ConnectionSettings) -> Self {
+        let proxy = settings.get("proxy");
+
+        if setting_value::<i32>(proxy, "method").unwrap_or(METHOD_NONE) != METHOD_AUTO {
+            return Self::None;
+        }
+
+        let pac_script = setting_value::<String>(proxy, "pac-script").unwrap_or_default();
+        if let Some(manual) = ManualProxy::from_pac_script(&pac_script) {
+            return Self::Manual(manual);
+        }
+
+        Self::Auto {
+            pac_url: setting_value::<String>(proxy, "pac-url").filter(|url| !url.is_empty()),
+        }
+    }
+
+    /// The `proxy` setting of a connection profile.
+    pub fn setting(&self) -> Result<HashMap<&'static str, Value<'static>>, Error> {
+        let (method, pac_url, pac_script) = match self {
+            Self::None => (METHOD_NONE, String::new(), String::new()),
+            Self::Auto { pac_url } => (
+                METHOD_AUTO,
+                pac_url.clone().unwrap_or_default(),
+                String::new(),
+            ),
+            Self::Manual(manual) => (METHOD_AUTO, String::new(), manual.pac_script()?),
+        };
+
+        Ok(HashMap::from([
+            ("method", Value::I32(method)),
+            ("pac-url", Value::from(pac_url)),
+            ("pac-script", Value::from(pac_script)),
+        ]))
+    }
+}
+
+impl ManualProxy {
+    /// Writes the servers as a PAC script.
+    ///
+    /// Fails with [`Error::InvalidProxy`] on a server which is not a `host:port`, or a host
+    /// pattern with other characters than those of host names and wildcards, as these are
+    /// written into the script as they are.
+    pub fn pac_script(&self) -> Result<String, Error> {
+        for server in [&self.http, &self.https, &self.socks].into_iter().flatten() {
+            if !valid_server(server) {
+                return Err(Error::InvalidProxy(server.clone()));
+            }
+        }
+
+        if let Some(host) = self
+            .ignore_hosts
+            .iter()
+            .find(|host| !valid_host_pattern(host))
+        {
+            return Err(Error::InvalidProxy(host.clone()));
+        }
+
+        let mut script = format!("{PAC_HEADER}\nfunction FindProxyForURL(url, host) {{\n");
+
+        for host in &self.ignore_hosts {
+            _ = writeln!(
+                script,
+                "  if (shExpMatch(host, \"{host}\")) return \"DIRECT\";"
+            );
+        }
+
+        if let Some(https) = self.https.as_deref() {
+            _ = writeln!(
+                script,
+                "  if (url.substring(0, 6) == \"https:\") return \"PROXY {https}\";"
+            );
+        }
+
+        if let Some(http) = self.http.as_deref() {
+            _ = writeln!(
+                script,
+                "  if (url.substring(0, 5) == \"http:\") return \"PROXY {http}\";"
+            );
+        }
+
+        match self.socks.as_deref() {
+            Some(socks) => _ = writeln!(script, "  return \"SOCKS {socks}\";"),
+            None => script.push_str("  return \"DIRECT\";\n"),
+        }
+
+        script.push_str("}\n");
+        Ok(script)
+    }
+
+    /// Reads a PAC script written by [`ManualProxy::pac_script`].
+    pub fn from_pac_script(script: &str) -> Option<Self> {
+        let mut lines = script.lines().map(str::trim);
+
+        if lines.next()? != PAC_HEADER {
+            return None;
+        }
+
+        let mut manual = Self::default();
+
+        for line in lines {
+            if let Some(host) = line
+                .strip_prefix("if (shExpMatch(host, \"")
+                .and_then(|rest| rest.strip_suffix("\")) return \"DIRECT\";"))
+            {
+                manual.ignore_hosts.push(host.to_owned());
+            } else if let Some(server) = line
+                .strip_prefix("if (url.substring(0, 6) == \"https:\") return \"PROXY ")
+                .and_then(|rest| rest.strip_suffix("\";"))
+            {
+                manual.https = Some(server.to_owned());
+            } else if let Some(server) = line
+                .strip_prefix("if (url.substring(0, 5) == \"http:\") return \"PROXY ")
+                .and_then(|rest| rest.strip_suffix("\";"))
+            {
+                manual.http = Some(server.to_owned());
+            } else if let Some(server) = line
+                .strip_prefix("return \"SOCKS ")
+                .and_then(|rest| rest.strip_suffix("\";"))
+            {
+                manual.socks = Some(server.to_owned());
+            }
+        }
+
+        Some(manual)
+    }
+}
+
+/// Whether a server is given as `host[:port]`, where the host may be a bracketed IPv6 address.
+fn valid_server(server: &str) -> bool {
+    let (host_valid, port) = match server.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((address, port)) => (
+                !address.is_empty() && address.chars().all(|c| c.is_ascii_hexdigit() || c == ':'),
+                port,
+            ),
+            None => return false,
+        },
+        None => {
+            let (host, port) = server.split_at(server.find(':').unwrap_or(server.len()));
+            (!host.is_empty() && host.chars().all(is_host_char), port)
+        }
+    };
+
+    let port_valid = match port.strip_prefix(':') {
+        Some(port) => port.parse::<u16>().is_ok_and(|port| port != 0),
+        None => port.is_empty(),
+    };
+
+    host_valid && port_valid
+}
+
+/// Whether a host pattern consists of host name characters and `shExpMatch` wildcards.
+fn valid_host_pattern(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && pattern
+            .chars()
+            .all(|c| is_host_char(c) || matches!(c, '*' | '?' | ':'))
+}
+
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')
+}
+
+/// Reads the proxy settings of a profile.
+pub async fn get(conn: &zbus::Connection, uuid: &str) -> Result<ProxyConfig, Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+
+    Ok(ProxyConfig::from_settings(&settings))
+}
+
+/// Replaces the proxy settings of a profile, reapplying it to any device it is active on.
+pub async fn set(conn: &zbus::Connection, uuid: &str, config: &ProxyConfig) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    update.insert("proxy", config.setting()?);
+    connection.update(update).await?;
+
+    reapply(conn, uuid).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::OwnedValue;
+
+    fn round_trip(config: &ProxyConfig) -> ProxyConfig {
+        let proxy = config
+            .setting()
+            .unwrap()
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), OwnedValue::try_from(value).unwrap()))
+            .collect();
+
+        ProxyConfig::from_settings(&HashMap::from([(String::from("proxy"), proxy)]))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let manual = ProxyConfig::Manual(ManualProxy {
+            http: Some(String::from("proxy.example.com:3128")),
+            https: Some(String::from("proxy.example.com:3129")),
+            socks: None,
+            ignore_hosts: vec![String::from("localhost"), String::from("*.local")],
+        });
+
+        for config in [
+            ProxyConfig::None,
+            ProxyConfig::Auto { pac_url: None },
+            ProxyConfig::Auto {
+                pac_url: Some(String::from("http://wpad.example.com/wpad.dat")),
+            },
+            manual,
+            ProxyConfig::Manual(ManualProxy {
+                socks: Some(String::from("127.0.0.1:1080")),
+                ..ManualProxy::default()
+            }),
+        ] {
+            assert_eq!(round_trip(&config), config);
+        }
+
+        // Values are written into the script as they are, so anything else is refused.
+        for hostile in [
+            "proxy.example.com:3128\"; } function x() { return \"PROXY evil:80",
+            "proxy.example.com:3128\nreturn \"PROXY evil:80\";",
+            "proxy.example.com:http",
+            "[::1",
+        ] {
+            let manual = ManualProxy {
+                http: Some(String::from(hostile)),
+                ..ManualProxy::default()
+            };
+            assert!(matches!(manual.pac_script(), Err(Error::InvalidProxy(_))));
+        }
+
+        let manual = ManualProxy {
+            socks: Some(String::from("[fd00::1]:1080")),
+            ignore_hosts: vec![String::from("*.local\") return \"PROXY evil:80")],
+            ..ManualProxy::default()
+        };
+        assert!(matches!(manual.pac_script(), Err(Error::InvalidProxy(_))));
+        assert!(valid_server("[fd00::1]:1080"));
+
+        // Scripts written elsewhere are left to the PAC runner.
+        assert_eq!(
+            ManualProxy::from_pac_script(
+                "function FindProxyForURL(url, host) { return \"DIRECT\"; }"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::Error;
+use cosmic_dbus_networkmanager::{
+    active_connection::ActiveConnection,
+    device::Device,
+    interface::enums::{ActiveConnectionState, DeviceState},
+};
+use futures::{FutureExt, StreamExt, future::Either, stream::BoxStream};
+use std::{fmt, time::Duration};
+
+/// Upper bound on an activation; NetworkManager normally gives up sooner on its own.
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Why NetworkManager could not activate a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivationError {
+    pub reason: ActiveConnectionStateReason,
+    /// Reason given by the device the connection was activated on, which is usually more specific.
+    pub device_reason: Option<DeviceStateReason>,
+}
+
+impl ActivationError {
+    /// Whether the failure is best explained by a wrong password or key.
+    pub fn incorrect_password(&self) -> bool {
+        matches!(
+            self.device_reason,
+            Some(DeviceStateReason::NoSecrets | DeviceStateReason::SupplicantDisconnect)
+        ) || (self.device_reason.is_none()
+            && matches!(
+                self.reason,
+                ActiveConnectionStateReason::NoSecrets | ActiveConnectionStateReason::LoginFailed
+            ))
+    }
+}
+
+impl fmt::Display for ActivationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.incorrect_password() {
+            return f.write_str("the password or key was rejected");
+        }
+
+        match self.device_reason {
+            Some(reason) => reason.fmt(f),
+            None => self.reason.fmt(f),
+        }
+    }
+}
+
+/// `NMActiveConnectionStateReason`, which VPN connections report their state changes with too.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveConnectionStateReason {
+    #[default]
+    Unknown,
+    None,
+    UserDisconnected,
+    DeviceDisconnected,
+    ServiceStopped,
+    IpConfigInvalid,
+    ConnectTimeout,
+    ServiceStartTimeout,
+    ServiceStartFailed,
+    NoSecrets,
+    LoginFailed,
+    ConnectionRemoved,
+    DependencyFailed,
+    DeviceRealizeFailed,
+    DeviceRemoved,
+}
+
+impl From<u32> for ActiveConnectionStateReason {
+    fn from(reason: u32) -> Self {
+        match reason {
+            1 => Self::None,
+            2 => Self::UserDisconnected,
+            3 => Self::DeviceDisconnected,
+            4 => Self::ServiceStopped,
+            5 => Self::IpConfigInvalid,
+            6 => Self::ConnectTimeout,
+            7 => Self::ServiceStartTimeout,
+            8 => Self::ServiceStartFailed,
+            9 => Self::NoSecrets,
+            10 => Self::LoginFailed,
+            11 => Self::ConnectionRemoved,
+            12 => Self::DependencyFailed,
+            13 => Self::DeviceRealizeFailed,
+            14 => Self::DeviceRemoved,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ActiveConnectionStateReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unknown => "unknown reason",
+            Self::None => "no reason given",
+            Self::UserDisconnected => "disconnected by the user",
+            Self::DeviceDisconnected => "the device was disconnected",
+            Self::ServiceStopped => "the VPN service stopped",
+            Self::IpConfigInvalid => "the IP configuration was invalid",
+            Self::ConnectTimeout => "the connection timed out",
+            Self::ServiceStartTimeout => "the VPN service timed out while starting",
+            Self::ServiceStartFailed => "the VPN service failed to start",
+            Self::NoSecrets => "required secrets were not provided",
+            Self::LoginFailed => "login failed",
+            Self::ConnectionRemoved => "the connection profile was removed",
+            Self::DependencyFailed => "a connection it depends on failed",
+            Self::DeviceRealizeFailed => "the virtual device could not be created",
+            Self::DeviceRemoved => "the virtual device was removed",
+        })
+    }
+}
+
+/// `NMDeviceStateReason`, for the reasons a connection attempt can fail with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceStateReason {
+    None,
+    #[default]
+    Unknown,
+    /// The device became managed by NetworkManager.
+    NowManaged,
+    /// The device is no longer managed by NetworkManager.
+    NowUnmanaged,
+    ConfigFailed,
+    IpConfigUnavailable,
+    IpConfigExpired,
+    /// Secrets were required but not provided, as when the password is rejected.
+    NoSecrets,
+    /// The supplicant disconnected, typically after a wrong Wi-Fi password.
+    SupplicantDisconnect,
+    SupplicantConfigFailed,
+    SupplicantFailed,
+    SupplicantTimeout,
+    PppFailed,
+    DhcpStartFailed,
+    DhcpError,
+    DhcpFailed,
+    SharedFailed,
+    ModemFailed,
+    GsmRegistrationFailed,
+    GsmSimPinRequired,
+    FirmwareMissing,
+    Removed,
+    Sleeping,
+    UserRequested,
+    /// The cable was unplugged or the link went down.
+    CarrierLost,
+    DependencyFailed,
+    SsidNotFound,
+    IpAddressDuplicate,
+    /// Any other `NMDeviceStateReason` value.
+    Other(u32),
+}
+
+impl From<u32> for DeviceStateReason {
+    fn from(reason: u32) -> Self {
+        match reason {
+            0 => Self::None,
+            1 => Self::Unknown,
+            2 => Self::NowManaged,
+            3 => Self::NowUnmanaged,
+            4 => Self::ConfigFailed,
+            5 => Self::IpConfigUnavailable,
+            6 => Self::IpConfigExpired,
+            7 => Self::NoSecrets,
+            8 => Self::SupplicantDisconnect,
+            9 => Self::SupplicantConfigFailed,
+            10 => Self::SupplicantFailed,
+            11 => Self::SupplicantTimeout,
+            12..=14 => Self::PppFailed,
+            15 => Self::DhcpStartFailed,
+            16 => Self::DhcpError,
+            17 => Self::DhcpFailed,
+            18 | 19 => Self::SharedFailed,
+            23..=28 | 57 => Self::ModemFailed,
+            29..=33 => Self::GsmRegistrationFailed,
+            46 => Self::GsmSimPinRequired,
+            35 => Self::FirmwareMissing,
+            36 => Self::Removed,
+            37 => Self::Sleeping,
+            39 => Self::UserRequested,
+            40 => Self::CarrierLost,
+            50 => Self::DependencyFailed,
+            53 => Self::SsidNotFound,
+            64 => Self::IpAddressDuplicate,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for DeviceStateReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "no reason given",
+            Self::Unknown => "unknown reason",
+            Self::NowManaged => "the device became managed",
+            Self::NowUnmanaged => "the device is no longer managed",
+            Self::ConfigFailed => "the device could not be configured",
+            Self::IpConfigUnavailable => "no IP configuration could be obtained",
+            Self::IpConfigExpired => "the IP configuration expired",
+            Self::NoSecrets => "required secrets were not provided",
+            Self::SupplicantDisconnect => "the supplicant disconnected",
+            Self::SupplicantConfigFailed => "the supplicant could not be configured",
+            Self::SupplicantFailed => "the supplicant failed",
+            Self::SupplicantTimeout => "the supplicant timed out",
+            Self::PppFailed => "the PPP session failed",
+            Self::DhcpStartFailed => "the DHCP client could not be started",
+            Self::DhcpError => "the DHCP client failed",
+            Self::DhcpFailed => "no DHCP lease was obtained",
+            Self::SharedFailed => "the connection could not be shared",
+            Self::ModemFailed => "the modem failed",
+            Self::GsmRegistrationFailed => "the modem could not register with the network",
+            Self::GsmSimPinRequired => "the SIM PIN is required",
+            Self::FirmwareMissing => "the device firmware is missing",
+            Self::Removed => "the device was removed",
+            Self::Sleeping => "the system is going to sleep",
+            Self::UserRequested => "disconnected by the user",
+            Self::CarrierLost => "the cable was unplugged",
+            Self::DependencyFailed => "a connection it depends on failed",
+            Self::SsidNotFound => "the network was not found",
+            Self::IpAddressDuplicate => "the IP address is already in use",
+            Self::Other(reason) => return write!(f, "device state reason {reason}"),
+        })
+    }
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.Connection.Active",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait ActiveConnectionSignals {
+    #[zbus(signal)]
+    fn state_changed(&self, state: u32, reason: u32) -> zbus::Result<()>;
+}
+
+mod device {
+    // A module of its own, as the signal shares its name with the active connection's.
+    #[zbus::proxy(
+        interface = "org.freedesktop.NetworkManager.Device",
+        default_service = "org.freedesktop.NetworkManager"
+    )]
+    pub(super) trait DeviceSignals {
+        #[zbus(signal)]
+        fn state_changed(&self, new_state: u32, old_state: u32, reason: u32) -> zbus::Result<()>;
+    }
+}
+
+/// Follows a connection attempt on a device, to report why it failed.
+///
+/// Must be created before the connection is activated, as NetworkManager resets the device's
+/// `StateReason` once the failed connection is torn down.
+pub(super) struct ActivationWatch {
+    device_changes: BoxStream<'static, device::StateChanged>,
+    device_reason: Option<DeviceStateReason>,
+}
+
+impl ActivationWatch {
+    pub(super) async fn new(device: &Device<'_>) -> Result<Self, Error> {
+        let signals = device::DeviceSignalsProxy::builder(device.inner().connection())
+            .path(device.inner().path().to_owned())?
+            .build()
+            .await?;
+
+        Ok(Self {
+            device_changes: signals.receive_state_changed().await?.boxed(),
+            device_reason: None,
+        })
+    }
+
+    /// Follows a connection which is not bound to a device, such as a VPN.
+    pub(super) fn without_device() -> Self {
+        Self {
+            device_changes: futures::stream::empty().boxed(),
+            device_reason: None,
+        }
+    }
+
+    /// Waits for the connection to finish activating, returning NetworkManager's reason if it
+    /// fails.
+    pub(super) async fn wait(mut self, active_conn: &ActiveConnection<'_>) -> Result<(), Error> {
+        let signals = ActiveConnectionSignalsProxy::builder(active_conn.inner().connection())
+            .path(active_conn.inner().path().to_owned())?
+            .build()
+            .await?;
+        let mut changes = signals.receive_state_changed().await?;
+
+        // The connection may have settled before the signal was subscribed to.
+        match active_conn.state().await? {
+            ActiveConnectionState::Activated => return Ok(()),
+            ActiveConnectionState::Deactivated => {
+                return Err(self.error(ActiveConnectionStateReason::Unknown));
+            }
+            _ => (),
+        }
+
+        let result = tokio::time::timeout(ACTIVATION_TIMEOUT, async {
+            loop {
+                let change =
+                    match futures::future::select(changes.next(), self.device_changes.next()).await
+                    {
+                        Either::Left((Some(change), _)) => change,
+                        Either::Left((None, _)) => return Err(Error::ConnectionActivate),
+                        Either::Right((Some(change), _)) => {
+                            self.device_changed(&change);
+                            continue;
+                        }
+                        // Without the device's signals, the connection's reason has to do.
+                        Either::Right((None, _)) => match changes.next().await {
+                            Some(change) => change,
+                            None => return Err(Error::ConnectionActivate),
+                        },
+                    };
+
+                let Ok(args) = change.args() else {
+                    continue;
+                };
+
+                match ActiveConnectionState::from(*args.state()) {
+                    ActiveConnectionState::Activated => return Ok(()),
+                    ActiveConnectionState::Deactivated => {
+                        let reason = ActiveConnectionStateReason::from(*args.reason());
+                        return Err(self.error(reason));
+                    }
+                    _ => (),
+                }
+            }
+        })
+        .await;
+
+        result.unwrap_or(Err(Error::ConnectionActivate))
+    }
+
+    /// Records the reason of a transition into the failed state.
+    fn device_changed(&mut self, change: &device::StateChanged) {
+        let Ok(args) = change.args() else {
+            return;
+        };
+
+        if DeviceState::from(*args.new_state()) == DeviceState::Failed {
+            self.device_reason = Some(DeviceStateReason::from(*args.reason())).filter(|reason| {
+                !matches!(reason, DeviceStateReason::None | DeviceStateReason::Unknown)
+            });
+        }
+    }
+
+    fn error(mut self, reason: ActiveConnectionStateReason) -> Error {
+        // The device fails before the connection is deactivated, so its signal is already queued.
+        while let Some(Some(change)) = self.device_changes.next().now_or_never() {
+            self.device_changed(&change);
+        }
+
+        Error::Activation(ActivationError {
+            reason,
+            device_reason: self.device_reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incorrect_password() {
+        let error = |reason: u32, device_reason: Option<u32>| ActivationError {
+            reason: ActiveConnectionStateReason::from(reason),
+            device_reason: device_reason.map(DeviceStateReason::from),
+        };
+
+        // NM_DEVICE_STATE_REASON_NO_SECRETS after a rejected PSK
+        assert!(error(9, Some(7)).incorrect_password());
+        assert!(error(3, Some(8)).incorrect_password());
+        // NM_DEVICE_STATE_REASON_DHCP_FAILED
+        assert!(!error(3, Some(17)).incorrect_password());
+        assert_eq!(error(3, Some(17)).to_string(), "no DHCP lease was obtained");
+        // VPN plugins report rejected credentials without a device.
+        assert!(error(10, None).incorrect_password());
+        assert_eq!(DeviceStateReason::from(40), DeviceStateReason::CarrierLost);
+        assert_eq!(DeviceStateReason::from(99), DeviceStateReason::Other(99));
+    }
+}
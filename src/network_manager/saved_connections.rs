@@ -0,0 +1,108 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{Error, SSID, UUID, find_connection, setting_value, settings_for_update};
+use cosmic_dbus_networkmanager::{nm::NetworkManager, settings::NetworkManagerSettings};
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime},
+};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+
+/// A connection profile saved in NetworkManager, whether or not it can be used right now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedConnection {
+    pub path: ObjectPath<'static>,
+    pub uuid: UUID,
+    pub id: String,
+    /// NetworkManager's connection type, such as `802-11-wireless` or `802-3-ethernet`.
+    pub connection_type: String,
+    pub ssid: Option<SSID>,
+    /// When the profile was last activated successfully.
+    pub last_used: Option<SystemTime>,
+    pub autoconnect: bool,
+    /// Profiles with a higher priority are preferred when autoconnecting.
+    pub autoconnect_priority: i32,
+    /// Whether a device could activate the profile now, as when its network is in range.
+    pub visible: bool,
+}
+
+/// Lists every saved connection profile, most recently used first.
+pub async fn list(conn: &zbus::Connection) -> Result<Vec<SavedConnection>, Error> {
+    let nm = NetworkManager::new(conn).await?;
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    let mut available = HashSet::new();
+    for device in nm.devices().await? {
+        let paths = device
+            .inner()
+            .get_property::<Vec<OwnedObjectPath>>("AvailableConnections")
+            .await
+            .unwrap_or_default();
+        available.extend(paths);
+    }
+
+    let mut saved = Vec::new();
+    for connection in nm_settings.list_connections().await? {
+        let Ok(settings) = connection.get_settings().await else {
+            continue;
+        };
+
+        let path = OwnedObjectPath::from(connection.inner().path().to_owned());
+        let connection_setting = settings.get("connection");
+
+        saved.push(SavedConnection {
+            visible: available.contains(&path),
+            path: path.into_inner(),
+            uuid: setting_value::<String>(connection_setting, "uuid")
+                .map(UUID::from)
+                .unwrap_or_default(),
+            id: setting_value(connection_setting, "id").unwrap_or_default(),
+            connection_type: setting_value(connection_setting, "type").unwrap_or_default(),
+            ssid: setting_value::<Vec<u8>>(settings.get("802-11-wireless"), "ssid").map(SSID::from),
+            last_used: setting_value::<u64>(connection_setting, "timestamp")
+                .filter(|timestamp| *timestamp != 0)
+                .map(|timestamp| SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp)),
+            autoconnect: setting_value(connection_setting, "autoconnect").unwrap_or(true),
+            autoconnect_priority: setting_value(connection_setting, "autoconnect-priority")
+                .unwrap_or_default(),
+        });
+    }
+
+    saved.sort_by_key(|saved| std::cmp::Reverse(saved.last_used));
+    Ok(saved)
+}
+
+/// Sets whether NetworkManager may activate a profile on its own.
+pub async fn set_autoconnect(
+    conn: &zbus::Connection,
+    uuid: &str,
+    autoconnect: bool,
+) -> Result<(), Error> {
+    set_connection_setting(conn, uuid, "autoconnect", Value::Bool(autoconnect)).await
+}
+
+/// Sets which profiles NetworkManager prefers when several can autoconnect.
+pub async fn set_autoconnect_priority(
+    conn: &zbus::Connection,
+    uuid: &str,
+    priority: i32,
+) -> Result<(), Error> {
+    set_connection_setting(conn, uuid, "autoconnect-priority", Value::I32(priority)).await
+}
+
+async fn set_connection_setting(
+    conn: &zbus::Connection,
+    uuid: &str,
+    key: &'static str,
+    value: Value<'static>,
+) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    update.entry("connection").or_default().insert(key, value);
+    connection.update(update).await?;
+
+    Ok(())
+}
@@ -0,0 +1,317 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{ConnectionSettings, Event, UUID, setting_value};
+use futures::{SinkExt, StreamExt, channel::oneshot};
+use secure_string::SecureString;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+/// NetworkManager only looks for secret agents at this path.
+const AGENT_PATH: &str = "/org/freedesktop/NetworkManager/SecretAgent";
+const AGENT_IDENTIFIER: &str = "com.system76.CosmicSettings";
+
+/// `NM_SECRET_AGENT_CAPABILITY_VPN_HINTS`
+const CAPABILITY_VPN_HINTS: u32 = 0x1;
+
+// `NMSecretAgentGetSecretsFlags`
+const FLAG_ALLOW_INTERACTION: u32 = 0x1;
+const FLAG_REQUEST_NEW: u32 = 0x2;
+
+/// VPN secrets handed over with [`super::Request::ActivateVpn`], keyed by profile UUID.
+///
+/// The agent answers NetworkManager's next request for them without prompting, so they are never
+/// written to the profile. They are withdrawn once the activation succeeds or fails.
+static PROVIDED_VPN_SECRETS: LazyLock<Mutex<HashMap<UUID, HashMap<String, SecureString>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Hands VPN secrets to the agent for the next activation of a profile.
+pub(super) fn provide_vpn_secrets(uuid: UUID, secrets: HashMap<String, SecureString>) {
+    PROVIDED_VPN_SECRETS.lock().unwrap().insert(uuid, secrets);
+}
+
+/// Drops the secrets of a profile once its activation settled, whether they were used or not.
+pub(super) fn withdraw_vpn_secrets(uuid: &str) {
+    PROVIDED_VPN_SECRETS.lock().unwrap().remove(uuid);
+}
+
+/// Secrets for a setting, keyed by setting key, or `None` if the user declined to provide them.
+pub type SecretsReply = Option<HashMap<String, SecureString>>;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// NetworkManager needs secrets to activate a connection.
+    GetSecrets(Arc<SecretsRequest>),
+    /// NetworkManager no longer needs the secrets of an earlier request.
+    CancelGetSecrets {
+        connection_path: OwnedObjectPath,
+        setting_name: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct SecretsRequest {
+    pub connection_path: OwnedObjectPath,
+    pub uuid: UUID,
+    pub id: String,
+    pub connection_type: String,
+    /// The setting the secrets belong to, such as `802-11-wireless-security` or `vpn`.
+    pub setting_name: String,
+    /// Keys of the secrets which are needed, when NetworkManager knows them.
+    pub hints: Vec<String>,
+    /// The saved secrets were rejected, as when a Wi-Fi password has changed.
+    pub request_new: bool,
+    reply: Mutex<Option<oneshot::Sender<SecretsReply>>>,
+}
+
+impl SecretsRequest {
+    /// Replies to NetworkManager. Only the first reply to a request is used.
+    pub fn reply(&self, secrets: SecretsReply) {
+        if let Some(reply) = self.reply.lock().unwrap().take() {
+            _ = reply.send(secrets);
+        }
+    }
+
+    fn cancel(&self) {
+        self.reply.lock().unwrap().take();
+    }
+}
+
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.freedesktop.NetworkManager.SecretAgent")]
+pub enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    UserCanceled(String),
+    NoSecrets(String),
+}
+
+struct SecretAgent {
+    tx: futures::channel::mpsc::Sender<Message>,
+    pending: Mutex<Vec<Arc<SecretsRequest>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.SecretAgent")]
+impl SecretAgent {
+    async fn get_secrets(
+        &self,
+        connection: ConnectionSettings,
+        connection_path: OwnedObjectPath,
+        setting_name: String,
+        hints: Vec<String>,
+        flags: u32,
+    ) -> Result<ConnectionSettings, AgentError> {
+        let connection_setting = connection.get("connection");
+        let uuid = setting_value::<String>(connection_setting, "uuid")
+            .map(UUID::from)
+            .unwrap_or_default();
+
+        let provided = match setting_name.as_str() {
+            "vpn" => PROVIDED_VPN_SECRETS.lock().unwrap().remove(&uuid),
+            _ => None,
+        };
+
+        if let Some(secrets) = provided {
+            return Ok(secrets_setting(&setting_name, secrets));
+        }
+
+        if flags & FLAG_ALLOW_INTERACTION == 0 {
+            return Err(AgentError::NoSecrets(String::from(
+                "interaction is required to provide secrets",
+            )));
+        }
+
+        let (reply, response) = oneshot::channel();
+        let request = Arc::new(SecretsRequest {
+            connection_path,
+            uuid,
+            id: setting_value(connection_setting, "id").unwrap_or_default(),
+            connection_type: setting_value(connection_setting, "type").unwrap_or_default(),
+            setting_name,
+            hints,
+            request_new: flags & FLAG_REQUEST_NEW != 0,
+            reply: Mutex::new(Some(reply)),
+        });
+
+        self.pending.lock().unwrap().push(request.clone());
+        _ = self
+            .tx
+            .clone()
+            .send(Message::GetSecrets(request.clone()))
+            .await;
+
+        let secrets = response.await;
+
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|pending| !Arc::ptr_eq(pending, &request));
+
+        match secrets {
+            Ok(Some(secrets)) => Ok(secrets_setting(&request.setting_name, secrets)),
+            _ => Err(AgentError::UserCanceled(String::from(
+                "secrets were not provided",
+            ))),
+        }
+    }
+
+    async fn cancel_get_secrets(&self, connection_path: OwnedObjectPath, setting_name: String) {
+        self.pending.lock().unwrap().retain(|pending| {
+            let matches =
+                pending.connection_path == connection_path && pending.setting_name == setting_name;
+            if matches {
+                pending.cancel();
+            }
+            !matches
+        });
+
+        _ = self
+            .tx
+            .clone()
+            .send(Message::CancelGetSecrets {
+                connection_path,
+                setting_name,
+            })
+            .await;
+    }
+
+    /// Secrets are stored by NetworkManager or the user's keyring, not by this agent.
+    async fn save_secrets(
+        &self,
+        _connection: ConnectionSettings,
+        _connection_path: OwnedObjectPath,
+    ) {
+    }
+
+    async fn delete_secrets(
+        &self,
+        _connection: ConnectionSettings,
+        _connection_path: OwnedObjectPath,
+    ) {
+    }
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.AgentManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager/AgentManager"
+)]
+trait AgentManager {
+    fn register_with_capabilities(&self, identifier: &str, capabilities: u32) -> zbus::Result<()>;
+
+    fn unregister(&self) -> zbus::Result<()>;
+}
+
+/// Arranges secrets in the form `GetSecrets` returns them.
+///
+/// VPN plugins expect their secrets as a string dictionary under the `secrets` key.
+fn secrets_setting(
+    setting_name: &str,
+    secrets: HashMap<String, SecureString>,
+) -> ConnectionSettings {
+    let setting = if setting_name == "vpn" {
+        let secrets: HashMap<String, String> = secrets
+            .into_iter()
+            .map(|(key, secret)| (key, secret.unsecure().to_owned()))
+            .collect();
+
+        OwnedValue::try_from(Value::from(secrets))
+            .map(|secrets| HashMap::from([(String::from("secrets"), secrets)]))
+            .unwrap_or_default()
+    } else {
+        secrets
+            .into_iter()
+            .filter_map(|(key, secret)| {
+                let value = OwnedValue::try_from(Value::from(secret.unsecure())).ok()?;
+                Some((key, value))
+            })
+            .collect()
+    };
+
+    HashMap::from([(setting_name.to_owned(), setting)])
+}
+
+pub async fn unregister(connection: zbus::Connection) -> zbus::Result<()> {
+    AgentManagerProxy::new(&connection)
+        .await?
+        .unregister()
+        .await
+}
+
+/// Serves a secret agent on `connection`, forwarding its requests as [`Event::SecretAgent`].
+///
+/// Reply to a [`Message::GetSecrets`] with [`SecretsRequest::reply`].
+pub async fn watch(
+    connection: zbus::Connection,
+    mut tx: futures::channel::mpsc::Sender<Event>,
+) -> zbus::Result<()> {
+    let span = tracing::span!(tracing::Level::INFO, "network_manager::secret_agent::watch");
+    let _span = span.enter();
+
+    let (agent_tx, mut receiver) = futures::channel::mpsc::channel(4);
+    let agent = SecretAgent {
+        tx: agent_tx,
+        pending: Mutex::new(Vec::new()),
+    };
+
+    let agent_path = ObjectPath::from_static_str_unchecked(AGENT_PATH);
+
+    tracing::debug!("serving agent");
+
+    connection.object_server().at(&agent_path, agent).await?;
+
+    tracing::debug!("registering agent");
+
+    let agent_manager = AgentManagerProxy::new(&connection).await?;
+    agent_manager
+        .register_with_capabilities(AGENT_IDENTIFIER, CAPABILITY_VPN_HINTS)
+        .await?;
+
+    tracing::debug!("registered");
+
+    while let Some(msg) = receiver.next().await {
+        tracing::debug!(?msg, "agent message received");
+
+        if tx.send(Event::SecretAgent(msg)).await.is_err() {
+            break;
+        }
+    }
+
+    _ = agent_manager.unregister().await;
+    _ = connection
+        .object_server()
+        .remove::<SecretAgent, _>(&agent_path)
+        .await;
+
+    tracing::debug!("exiting");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vpn_secrets_setting() {
+        let secrets = HashMap::from([(String::from("password"), SecureString::from("hunter2"))]);
+        let settings = secrets_setting("vpn", secrets);
+
+        let vpn_secrets =
+            HashMap::<String, String>::try_from(settings["vpn"]["secrets"].try_clone().unwrap())
+                .unwrap();
+        assert_eq!(vpn_secrets["password"], "hunter2");
+
+        let secrets = HashMap::from([(String::from("psk"), SecureString::from("hunter2"))]);
+        let settings = secrets_setting("802-11-wireless-security", secrets);
+        assert_eq!(
+            settings["802-11-wireless-security"]["psk"]
+                .downcast_ref::<String>()
+                .unwrap(),
+            "hunter2"
+        );
+    }
+}
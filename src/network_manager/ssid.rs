@@ -0,0 +1,109 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{borrow::Cow, fmt, sync::Arc};
+
+/// A Wi-Fi network name, kept as the raw bytes broadcast by the access point.
+///
+/// SSIDs are not required to be UTF-8, so the bytes are what NetworkManager must be given
+/// back when connecting. Use [`Ssid::to_string_lossy`] or `Display` to show them.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ssid(Arc<[u8]>);
+
+impl Ssid {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The SSID as text, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    /// The SSID as text, with invalid UTF-8 replaced by `U+FFFD`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+}
+
+impl fmt::Display for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+impl fmt::Debug for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_str() {
+            Some(ssid) => fmt::Debug::fmt(ssid, f),
+            None => write!(f, "Ssid({:02x?})", self.as_bytes()),
+        }
+    }
+}
+
+impl AsRef<[u8]> for Ssid {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&[u8]> for Ssid {
+    fn from(ssid: &[u8]) -> Self {
+        Self(Arc::from(ssid))
+    }
+}
+
+impl From<Vec<u8>> for Ssid {
+    fn from(ssid: Vec<u8>) -> Self {
+        Self(Arc::from(ssid))
+    }
+}
+
+impl From<&str> for Ssid {
+    fn from(ssid: &str) -> Self {
+        Self::from(ssid.as_bytes())
+    }
+}
+
+impl From<String> for Ssid {
+    fn from(ssid: String) -> Self {
+        Self::from(ssid.into_bytes())
+    }
+}
+
+impl PartialEq<[u8]> for Ssid {
+    fn eq(&self, other: &[u8]) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<str> for Ssid {
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_utf8_ssid_keeps_bytes() {
+        let bytes = b"caf\xe9".to_vec();
+        let ssid = Ssid::from(bytes.clone());
+
+        assert_eq!(ssid.as_bytes(), bytes.as_slice());
+        assert_eq!(ssid.as_str(), None);
+        assert_eq!(ssid.to_string(), "caf\u{fffd}");
+        assert_ne!(ssid, Ssid::from(ssid.to_string()));
+        assert_eq!(format!("{ssid:?}"), "Ssid([63, 61, 66, e9])");
+    }
+
+    #[test]
+    fn test_utf8_ssid() {
+        let ssid = Ssid::from("Café");
+        assert_eq!(ssid.as_str(), Some("Café"));
+        assert!(ssid == *"Café");
+        assert_eq!(format!("{ssid:?}"), "\"Café\"");
+    }
+}
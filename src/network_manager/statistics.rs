@@ -0,0 +1,300 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::Event;
+use cosmic_dbus_networkmanager::{
+    device::Device, interface::enums::DeviceType, nm::NetworkManager,
+};
+use futures::{SinkExt, StreamExt, stream::AbortHandle, stream::BoxStream};
+use iced_futures::{Subscription, stream};
+use std::{collections::HashMap, fmt::Debug, hash::Hash, time::Duration};
+use tokio::time::Instant;
+use tokio_stream::wrappers::IntervalStream;
+use zbus::{Connection, zvariant::ObjectPath};
+
+const STATISTICS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Statistics";
+
+/// Traffic counters of a network interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStatistics {
+    pub path: ObjectPath<'static>,
+    pub interface: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Bytes received per second since the previous sample.
+    pub rx_rate: u64,
+    /// Bytes sent per second since the previous sample.
+    pub tx_rate: u64,
+}
+
+/// Emits [`Event::Statistics`] for every device each `interval`.
+///
+/// NetworkManager only updates the counters of devices whose `RefreshRateMs` is set, so the
+/// subscription sets it to `interval` on each device, and restores the previous rate once it ends.
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: Connection,
+    interval: Duration,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, interval, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+pub async fn watch(
+    conn: zbus::Connection,
+    interval: Duration,
+    mut output: futures::channel::mpsc::Sender<Event>,
+) {
+    let network_manager = match NetworkManager::new(&conn).await {
+        Ok(n) => n,
+        Err(why) => {
+            tracing::error!(why = why.to_string(), "Failed to connect to NetworkManager");
+            return;
+        }
+    };
+
+    let refresh_rate_ms = u32::try_from(interval.as_millis()).unwrap_or(u32::MAX);
+
+    let mut messages = futures::stream::SelectAll::<BoxStream<'static, Message>>::new();
+    messages.push(
+        IntervalStream::new(tokio::time::interval(interval))
+            .map(|_| Message::Tick)
+            .boxed(),
+    );
+    messages.push(
+        network_manager
+            .receive_devices_changed()
+            .await
+            .map(|_| Message::DevicesChanged)
+            .boxed(),
+    );
+
+    let mut devices = HashMap::<ObjectPath<'static>, WatchedDevice>::new();
+    sync_devices(
+        &network_manager,
+        refresh_rate_ms,
+        &mut devices,
+        &mut messages,
+    )
+    .await;
+
+    while let Some(message) = messages.next().await {
+        match message {
+            Message::Tick => {
+                let now = Instant::now();
+                let mut statistics = devices
+                    .iter_mut()
+                    .filter_map(|(path, device)| device.sample(path, now))
+                    .collect::<Vec<_>>();
+                statistics.sort_by(|a, b| a.path.as_str().cmp(b.path.as_str()));
+
+                if output.send(Event::Statistics(statistics)).await.is_err() {
+                    break;
+                }
+            }
+
+            Message::DevicesChanged => {
+                sync_devices(
+                    &network_manager,
+                    refresh_rate_ms,
+                    &mut devices,
+                    &mut messages,
+                )
+                .await;
+            }
+
+            Message::RxBytes(path, bytes) => {
+                if let Some(device) = devices.get_mut(&path) {
+                    device.rx_bytes = Some(bytes);
+                }
+            }
+
+            Message::TxBytes(path, bytes) => {
+                if let Some(device) = devices.get_mut(&path) {
+                    device.tx_bytes = Some(bytes);
+                }
+            }
+        }
+    }
+}
+
+enum Message {
+    Tick,
+    DevicesChanged,
+    RxBytes(ObjectPath<'static>, u64),
+    TxBytes(ObjectPath<'static>, u64),
+}
+
+struct WatchedDevice {
+    counters: zbus::Proxy<'static>,
+    interface: String,
+    /// The `RefreshRateMs` which the device had before, if it was changed.
+    restore_refresh_rate: Option<u32>,
+    rx_bytes: Option<u64>,
+    tx_bytes: Option<u64>,
+    previous: Option<(Instant, u64, u64)>,
+    handle: AbortHandle,
+}
+
+impl WatchedDevice {
+    /// Enables the counters of a device, and streams their changes until dropped.
+    async fn new(
+        device: &Device<'_>,
+        refresh_rate_ms: u32,
+    ) -> zbus::Result<(Self, BoxStream<'static, Message>)> {
+        let path = device.inner().path().to_owned();
+        let counters = zbus::Proxy::new(
+            device.inner().connection(),
+            device.inner().destination().to_owned(),
+            path.clone(),
+            STATISTICS_INTERFACE,
+        )
+        .await?;
+
+        let restore_refresh_rate = match counters.get_property::<u32>("RefreshRateMs").await? {
+            rate if rate == refresh_rate_ms => None,
+            rate => {
+                counters
+                    .set_property("RefreshRateMs", refresh_rate_ms)
+                    .await
+                    .map_err(zbus::Error::from)?;
+                Some(rate)
+            }
+        };
+
+        let rx_path = path.clone();
+        let rx_changes = counters
+            .receive_property_changed::<u64>("RxBytes")
+            .await
+            .filter_map(move |change| {
+                let path = rx_path.clone();
+                async move { Some(Message::RxBytes(path, change.get().await.ok()?)) }
+            });
+
+        let tx_changes = counters
+            .receive_property_changed::<u64>("TxBytes")
+            .await
+            .filter_map(move |change| {
+                let path = path.clone();
+                async move { Some(Message::TxBytes(path, change.get().await.ok()?)) }
+            });
+
+        let (changes, handle) =
+            futures::stream::abortable(futures::stream::select(rx_changes, tx_changes));
+
+        let device = Self {
+            counters,
+            interface: device.interface().await.unwrap_or_default(),
+            restore_refresh_rate,
+            rx_bytes: None,
+            tx_bytes: None,
+            previous: None,
+            handle,
+        };
+
+        Ok((device, changes.boxed()))
+    }
+
+    /// The counters at `now`, with the rates since the previous sample.
+    fn sample(&mut self, path: &ObjectPath<'static>, now: Instant) -> Option<DeviceStatistics> {
+        let (rx_bytes, tx_bytes) = (self.rx_bytes?, self.tx_bytes?);
+
+        let (rx_rate, tx_rate) = match self.previous.replace((now, rx_bytes, tx_bytes)) {
+            Some((then, prev_rx, prev_tx)) => {
+                let elapsed = now - then;
+                (
+                    rate(prev_rx, rx_bytes, elapsed),
+                    rate(prev_tx, tx_bytes, elapsed),
+                )
+            }
+            None => (0, 0),
+        };
+
+        Some(DeviceStatistics {
+            path: path.clone(),
+            interface: self.interface.clone(),
+            rx_bytes,
+            tx_bytes,
+            rx_rate,
+            tx_rate,
+        })
+    }
+}
+
+impl Drop for WatchedDevice {
+    fn drop(&mut self) {
+        self.handle.abort();
+
+        let Some(rate) = self.restore_refresh_rate else {
+            return;
+        };
+
+        // Setting a property needs a runtime, which is gone if the application is exiting.
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let counters = self.counters.clone();
+        runtime.spawn(async move {
+            _ = counters.set_property("RefreshRateMs", rate).await;
+        });
+    }
+}
+
+/// Starts watching devices which were added, and stops watching those which were removed.
+async fn sync_devices(
+    network_manager: &NetworkManager<'_>,
+    refresh_rate_ms: u32,
+    watched: &mut HashMap<ObjectPath<'static>, WatchedDevice>,
+    messages: &mut futures::stream::SelectAll<BoxStream<'static, Message>>,
+) {
+    let devices = network_manager.devices().await.unwrap_or_default();
+
+    watched.retain(|path, _| devices.iter().any(|device| device.inner().path() == path));
+
+    for device in devices {
+        let path = device.inner().path().to_owned();
+        if watched.contains_key(&path)
+            || matches!(device.device_type().await, Ok(DeviceType::Loopback))
+        {
+            continue;
+        }
+
+        match WatchedDevice::new(&device, refresh_rate_ms).await {
+            Ok((watched_device, changes)) => {
+                watched.insert(path, watched_device);
+                messages.push(changes);
+            }
+            Err(why) => tracing::warn!(?why, "failed to enable statistics on {path}"),
+        }
+    }
+}
+
+/// Bytes per second between two counter samples. A counter which went backwards was reset.
+fn rate(previous: u64, current: u64, elapsed: Duration) -> u64 {
+    let elapsed_ms = elapsed.as_millis();
+    if current < previous || elapsed_ms == 0 {
+        return 0;
+    }
+
+    u64::try_from(u128::from(current - previous) * 1000 / elapsed_ms).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate() {
+        assert_eq!(rate(1_000, 3_000, Duration::from_secs(2)), 1_000);
+        assert_eq!(rate(1_000, 1_500, Duration::from_millis(250)), 2_000);
+        // Counters are reset when a device is recreated.
+        assert_eq!(rate(5_000, 100, Duration::from_secs(1)), 0);
+        assert_eq!(rate(0, 100, Duration::ZERO), 0);
+    }
+}
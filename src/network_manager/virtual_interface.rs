@@ -0,0 +1,360 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ConnectionSettings, Error, UUID, connection_settings, find_connection, setting_value,
+    settings_for_update,
+};
+use cosmic_dbus_networkmanager::settings::NetworkManagerSettings;
+use std::collections::HashMap;
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+/// The highest VLAN ID which may be assigned to traffic.
+const VLAN_ID_MAX: u16 = 4094;
+
+/// The kinds of virtual interfaces which can be configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VirtualInterfaceKind {
+    Bridge,
+    Bond(BondMode),
+    Vlan {
+        /// Interface name of the device carrying the tagged traffic.
+        parent: String,
+        id: u16,
+    },
+}
+
+impl VirtualInterfaceKind {
+    /// NetworkManager's connection type, which is also the name of its setting.
+    pub fn connection_type(&self) -> &'static str {
+        match self {
+            Self::Bridge => "bridge",
+            Self::Bond(_) => "bond",
+            Self::Vlan { .. } => "vlan",
+        }
+    }
+
+    /// Whether port profiles can be attached to the interface.
+    pub fn has_ports(&self) -> bool {
+        !matches!(self, Self::Vlan { .. })
+    }
+
+    fn from_settings(settings: &ConnectionSettings) -> Option<Self> {
+        let connection_type = setting_value::<String>(settings.get("connection"), "type")?;
+
+        match connection_type.as_str() {
+            "bridge" => Some(Self::Bridge),
+            "bond" => {
+                let mode =
+                    setting_value::<HashMap<String, String>>(settings.get("bond"), "options")
+                        .and_then(|options| BondMode::parse(options.get("mode")?))
+                        .unwrap_or_default();
+                Some(Self::Bond(mode))
+            }
+            "vlan" => {
+                let vlan = settings.get("vlan");
+                Some(Self::Vlan {
+                    parent: setting_value(vlan, "parent").unwrap_or_default(),
+                    id: setting_value::<u32>(vlan, "id")
+                        .and_then(|id| u16::try_from(id).ok())
+                        .unwrap_or_default(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn setting(&self) -> HashMap<&'static str, Value<'_>> {
+        match self {
+            Self::Bridge => HashMap::new(),
+            Self::Bond(mode) => HashMap::from([(
+                "options",
+                Value::from(HashMap::from([("mode", mode.as_str())])),
+            )]),
+            Self::Vlan { parent, id } => HashMap::from([
+                ("parent", Value::from(parent.as_str())),
+                ("id", Value::U32(u32::from(*id))),
+            ]),
+        }
+    }
+}
+
+/// How a bond spreads traffic over its ports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BondMode {
+    /// Round-robin over every port.
+    #[default]
+    BalanceRr,
+    /// One port is used at a time, failing over to another.
+    ActiveBackup,
+    /// Ports are picked by a hash of the source and destination addresses.
+    BalanceXor,
+    /// Every frame is sent on every port.
+    Broadcast,
+    /// IEEE 802.3ad dynamic link aggregation, which the switch must support.
+    Ieee8023ad,
+    /// Outgoing traffic is balanced by load.
+    BalanceTlb,
+    /// Incoming and outgoing traffic is balanced by load.
+    BalanceAlb,
+}
+
+impl BondMode {
+    pub const ALL: [Self; 7] = [
+        Self::BalanceRr,
+        Self::ActiveBackup,
+        Self::BalanceXor,
+        Self::Broadcast,
+        Self::Ieee8023ad,
+        Self::BalanceTlb,
+        Self::BalanceAlb,
+    ];
+
+    /// The value of the kernel's `mode` bonding option.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::BalanceRr => "balance-rr",
+            Self::ActiveBackup => "active-backup",
+            Self::BalanceXor => "balance-xor",
+            Self::Broadcast => "broadcast",
+            Self::Ieee8023ad => "802.3ad",
+            Self::BalanceTlb => "balance-tlb",
+            Self::BalanceAlb => "balance-alb",
+        }
+    }
+
+    /// Reads a `mode` bonding option, which may be given by name or number.
+    pub fn parse(mode: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .enumerate()
+            .find_map(|(number, bond_mode)| {
+                (mode == bond_mode.as_str() || mode == number.to_string()).then_some(bond_mode)
+            })
+    }
+}
+
+/// Settings for a new virtual interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualInterfaceConfig {
+    /// Name of the connection profile.
+    pub name: String,
+    /// Name of the network interface to create.
+    pub interface: String,
+    pub kind: VirtualInterfaceKind,
+}
+
+impl VirtualInterfaceConfig {
+    fn settings(&self) -> HashMap<&'static str, HashMap<&'static str, Value<'_>>> {
+        let connection_type = self.kind.connection_type();
+
+        HashMap::from([
+            (
+                "connection",
+                HashMap::from([
+                    ("id", Value::from(self.name.as_str())),
+                    ("type", Value::from(connection_type)),
+                    ("interface-name", Value::from(self.interface.as_str())),
+                ]),
+            ),
+            (connection_type, self.kind.setting()),
+            ("ipv4", HashMap::from([("method", Value::from("auto"))])),
+            ("ipv6", HashMap::from([("method", Value::from("auto"))])),
+        ])
+    }
+}
+
+/// A saved bridge, bond or VLAN profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualInterface {
+    pub uuid: UUID,
+    pub id: String,
+    /// Name of the network interface, or empty if the profile does not set one.
+    pub interface: String,
+    pub kind: VirtualInterfaceKind,
+    /// Profiles attached to the interface as ports.
+    pub ports: Vec<Port>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub uuid: UUID,
+    pub id: String,
+    /// The interface the port profile is bound to, if any.
+    pub interface: Option<String>,
+}
+
+/// Lists the saved bridge, bond and VLAN profiles with their ports.
+pub async fn list(conn: &zbus::Connection) -> Result<Vec<VirtualInterface>, Error> {
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    let mut all_settings = Vec::new();
+    for connection in nm_settings.list_connections().await? {
+        if let Ok(settings) = connection.get_settings().await {
+            all_settings.push(settings);
+        }
+    }
+
+    let interfaces = all_settings
+        .iter()
+        .filter_map(|settings| {
+            let kind = VirtualInterfaceKind::from_settings(settings)?;
+            let connection = settings.get("connection");
+            let uuid = setting_value::<String>(connection, "uuid")?;
+            // A VLAN may leave its interface name to be derived from the parent and ID.
+            let interface =
+                setting_value::<String>(connection, "interface-name").unwrap_or_default();
+
+            let ports = all_settings
+                .iter()
+                .filter(|port| {
+                    controller(port).is_some_and(|controller| {
+                        controller == uuid || (!interface.is_empty() && controller == interface)
+                    })
+                })
+                .filter_map(|port| {
+                    let connection = port.get("connection");
+                    Some(Port {
+                        uuid: UUID::from(setting_value::<String>(connection, "uuid")?),
+                        id: setting_value(connection, "id").unwrap_or_default(),
+                        interface: setting_value(connection, "interface-name"),
+                    })
+                })
+                .collect();
+
+            Some(VirtualInterface {
+                uuid: UUID::from(uuid),
+                id: setting_value(connection, "id").unwrap_or_default(),
+                interface,
+                kind,
+                ports,
+            })
+        })
+        .collect();
+
+    Ok(interfaces)
+}
+
+/// The controller a port profile is attached to, by UUID or interface name.
+fn controller(settings: &ConnectionSettings) -> Option<String> {
+    let connection = settings.get("connection");
+    setting_value::<String>(connection, "controller")
+        .or_else(|| setting_value(connection, "master"))
+        .filter(|controller| !controller.is_empty())
+}
+
+/// Adds a new bridge, bond or VLAN profile, returning its UUID.
+pub async fn create(
+    conn: &zbus::Connection,
+    config: &VirtualInterfaceConfig,
+) -> Result<UUID, Error> {
+    match config.kind {
+        VirtualInterfaceKind::Vlan { id, .. } if id > VLAN_ID_MAX => {
+            return Err(Error::InvalidVlanId(id));
+        }
+        _ => (),
+    }
+
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    let path: OwnedObjectPath = nm_settings
+        .inner()
+        .call("AddConnection", &(config.settings(),))
+        .await?;
+
+    // NetworkManager generates the UUID when normalizing the new profile.
+    let settings = connection_settings(conn, path).await?;
+
+    setting_value::<String>(settings.get("connection"), "uuid")
+        .map(UUID::from)
+        .ok_or(Error::ConnectionNotFound)
+}
+
+/// Deletes a virtual interface profile, detaching its ports so that they remain usable on their
+/// own.
+pub async fn delete(conn: &zbus::Connection, uuid: &str) -> Result<(), Error> {
+    let interface = list(conn)
+        .await?
+        .into_iter()
+        .find(|interface| &*interface.uuid == uuid)
+        .ok_or(Error::ConnectionNotFound)?;
+
+    for port in &interface.ports {
+        detach_port(conn, &port.uuid).await?;
+    }
+
+    find_connection(conn, uuid).await?.delete().await?;
+
+    Ok(())
+}
+
+/// Attaches a profile as a port of a bridge or bond.
+///
+/// The port's own addressing is dropped, as the controller's applies instead.
+pub async fn attach_port(
+    conn: &zbus::Connection,
+    controller_uuid: &str,
+    port_uuid: &str,
+) -> Result<(), Error> {
+    let controller = find_connection(conn, controller_uuid).await?;
+    let kind = VirtualInterfaceKind::from_settings(&controller.get_settings().await?)
+        .filter(VirtualInterfaceKind::has_ports)
+        .ok_or(Error::NotPortController)?;
+
+    let port = find_connection(conn, port_uuid).await?;
+    let settings = port.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    update.remove("ipv4");
+    update.remove("ipv6");
+
+    let connection = update.entry("connection").or_default();
+    connection.remove("controller");
+    connection.remove("port-type");
+    connection.insert("master", Value::from(controller_uuid));
+    connection.insert("slave-type", Value::from(kind.connection_type()));
+
+    port.update(update).await?;
+
+    Ok(())
+}
+
+/// Detaches a port profile from its bridge or bond, making it a standalone profile again.
+pub async fn detach_port(conn: &zbus::Connection, port_uuid: &str) -> Result<(), Error> {
+    let port = find_connection(conn, port_uuid).await?;
+    let settings = port.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    let connection = update.entry("connection").or_default();
+    for key in ["controller", "port-type", "master", "slave-type"] {
+        connection.remove(key);
+    }
+
+    for name in ["ipv4", "ipv6"] {
+        update
+            .entry(name)
+            .or_default()
+            .insert("method", Value::from("auto"));
+    }
+
+    port.update(update).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bond_mode() {
+        for mode in BondMode::ALL {
+            assert_eq!(BondMode::parse(mode.as_str()), Some(mode));
+        }
+
+        assert_eq!(BondMode::parse("1"), Some(BondMode::ActiveBackup));
+        assert_eq!(BondMode::parse("4"), Some(BondMode::Ieee8023ad));
+        assert_eq!(BondMode::parse("7"), None);
+        assert_eq!(BondMode::parse("round-robin"), None);
+    }
+}
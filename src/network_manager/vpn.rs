@@ -0,0 +1,335 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    Error, Event, UUID,
+    reason::ActiveConnectionStateReason,
+    wireguard::{self, WireGuardConfig},
+};
+use cosmic_dbus_networkmanager::{nm::NetworkManager, settings::NetworkManagerSettings};
+use futures::{SinkExt, StreamExt, future::Either};
+use iced_futures::{Subscription, stream};
+use std::{fmt::Debug, hash::Hash, path::Path};
+use tokio::process::Command;
+use zbus::{Connection, zvariant::ObjectPath};
+
+/// A saved VPN or WireGuard connection profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VpnProfile {
+    pub id: String,
+    pub uuid: UUID,
+    pub path: ObjectPath<'static>,
+    pub kind: VpnKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VpnKind {
+    WireGuard,
+    OpenVpn,
+    /// Any other NetworkManager VPN plugin, identified by its service type.
+    Plugin(String),
+}
+
+impl VpnKind {
+    fn from_service_type(service_type: &str) -> Self {
+        match service_type {
+            "org.freedesktop.NetworkManager.openvpn" => Self::OpenVpn,
+            other => Self::Plugin(other.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VpnState {
+    #[default]
+    Unknown,
+    Prepare,
+    NeedAuth,
+    Connect,
+    IpConfigGet,
+    Activated,
+    Failed,
+    Disconnected,
+}
+
+impl From<u32> for VpnState {
+    fn from(state: u32) -> Self {
+        match state {
+            1 => Self::Prepare,
+            2 => Self::NeedAuth,
+            3 => Self::Connect,
+            4 => Self::IpConfigGet,
+            5 => Self::Activated,
+            6 => Self::Failed,
+            7 => Self::Disconnected,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VpnEvent {
+    /// The state of an active VPN connection changed. `reason` is `None` for the initial state.
+    StateChanged {
+        uuid: UUID,
+        name: String,
+        state: VpnState,
+        reason: Option<ActiveConnectionStateReason>,
+    },
+    /// A [`super::Request::ImportVpn`] succeeded, adding the profile with this UUID.
+    Imported { uuid: UUID },
+    /// The VPN is waiting for secrets such as a password or one-time code.
+    ///
+    /// Reply with [`super::Request::ActivateVpn`] to supply them through the agent served by
+    /// [`super::secret_agent::watch`].
+    SecretsRequired { uuid: UUID, name: String },
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.NetworkManager.VPN.Connection",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+pub trait VpnConnection {
+    #[zbus(signal)]
+    fn vpn_state_changed(&self, state: u32, reason: u32) -> zbus::Result<()>;
+
+    #[zbus(property, name = "VpnState")]
+    fn state(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn banner(&self) -> zbus::Result<String>;
+}
+
+/// Lists saved VPN and WireGuard connection profiles.
+pub async fn list(conn: &Connection) -> zbus::Result<Vec<VpnProfile>> {
+    let settings = NetworkManagerSettings::new(conn).await?;
+
+    let profiles = futures::stream::FuturesOrdered::from_iter(
+        settings
+            .list_connections()
+            .await?
+            .into_iter()
+            .map(|connection| async move {
+                let settings = connection.get_settings().await.ok()?;
+                let connection_settings = settings.get("connection")?;
+
+                let kind = match connection_settings
+                    .get("type")?
+                    .downcast_ref::<String>()
+                    .ok()?
+                    .as_str()
+                {
+                    "wireguard" => VpnKind::WireGuard,
+                    "vpn" => VpnKind::from_service_type(
+                        &settings
+                            .get("vpn")?
+                            .get("service-type")?
+                            .downcast_ref::<String>()
+                            .ok()?,
+                    ),
+                    _ => return None,
+                };
+
+                Some(VpnProfile {
+                    id: connection_settings
+                        .get("id")?
+                        .downcast_ref::<String>()
+                        .ok()?,
+                    uuid: UUID::from(
+                        connection_settings
+                            .get("uuid")?
+                            .downcast_ref::<String>()
+                            .ok()?,
+                    ),
+                    path: connection.inner().path().to_owned(),
+                    kind,
+                })
+            }),
+    )
+    .filter_map(|profile| async move { profile })
+    .collect()
+    .await;
+
+    Ok(profiles)
+}
+
+/// Imports an OpenVPN `.ovpn` or WireGuard `.conf` file as a new connection profile.
+pub async fn import(conn: &Connection, path: &Path) -> Result<UUID, Error> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ovpn") => (),
+        Some("conf") => {
+            let config =
+                std::fs::read_to_string(path).map_err(|why| Error::VpnImport(why.to_string()))?;
+            let name = path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .unwrap_or("wg0");
+
+            return wireguard::create(conn, &WireGuardConfig::from_wg_quick(name, &config)?).await;
+        }
+        _ => return Err(Error::UnsupportedVpnConfig),
+    };
+
+    let output = Command::new("nmcli")
+        .args(["connection", "import", "type", "openvpn", "file"])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|why| Error::VpnImport(why.to_string()))?;
+
+    if !output.status.success() {
+        return Err(Error::VpnImport(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    imported_uuid(&String::from_utf8_lossy(&output.stdout))
+        .map(UUID::from)
+        .ok_or_else(|| Error::VpnImport(String::from("imported profile was not found")))
+}
+
+/// The UUID in nmcli's `Connection 'name' (uuid) successfully added.` message.
+fn imported_uuid(stdout: &str) -> Option<&str> {
+    let (_, rest) = stdout.rsplit_once('(')?;
+    let (uuid, _) = rest.split_once(')')?;
+
+    (uuid.len() == 36 && uuid.chars().all(|c| c.is_ascii_hexdigit() || c == '-')).then_some(uuid)
+}
+
+pub fn subscription<I: 'static + Hash + Copy + Send + Sync + Debug>(
+    id: I,
+    conn: Connection,
+) -> iced_futures::Subscription<Event> {
+    Subscription::run_with_id(
+        id,
+        stream::channel(50, move |output| async move {
+            watch(conn, output).await;
+            futures::future::pending().await
+        }),
+    )
+}
+
+/// Follows the state of every active VPN connection.
+pub async fn watch(conn: Connection, mut output: futures::channel::mpsc::Sender<Event>) {
+    let network_manager = match NetworkManager::new(&conn).await {
+        Ok(n) => n,
+        Err(why) => {
+            tracing::error!(why = why.to_string(), "Failed to connect to NetworkManager");
+            return;
+        }
+    };
+
+    let mut active_conns_changed = network_manager.receive_active_connections_changed().await;
+
+    loop {
+        let mut vpn_changes = futures::stream::SelectAll::new();
+
+        for active in network_manager
+            .active_connections()
+            .await
+            .unwrap_or_default()
+        {
+            if !active.vpn().await.unwrap_or_default() {
+                continue;
+            }
+
+            let (Ok(name), Ok(uuid)) = futures::join!(active.id(), active.uuid()) else {
+                continue;
+            };
+
+            let uuid = UUID::from(uuid);
+
+            let Ok(vpn) = VpnConnectionProxy::new(&conn, active.inner().path().to_owned()).await
+            else {
+                continue;
+            };
+
+            if let Ok(state) = vpn.state().await {
+                for event in vpn_events(&uuid, &name, state.into(), None) {
+                    _ = output.send(Event::Vpn(event)).await;
+                }
+            }
+
+            let Ok(changes) = vpn.receive_vpn_state_changed().await else {
+                continue;
+            };
+
+            vpn_changes.push(
+                changes
+                    .filter_map(move |signal| {
+                        let (uuid, name) = (uuid.clone(), name.clone());
+                        async move {
+                            let args = signal.args().ok()?;
+                            Some(vpn_events(
+                                &uuid,
+                                &name,
+                                VpnState::from(*args.state()),
+                                Some(ActiveConnectionStateReason::from(*args.reason())),
+                            ))
+                        }
+                    })
+                    .boxed(),
+            );
+        }
+
+        loop {
+            match futures::future::select(vpn_changes.next(), active_conns_changed.next()).await {
+                Either::Left((Some(events), _)) => {
+                    for event in events {
+                        _ = output.send(Event::Vpn(event)).await;
+                    }
+                }
+                Either::Left((None, _)) => {
+                    if active_conns_changed.next().await.is_none() {
+                        return;
+                    }
+                    break;
+                }
+                Either::Right((Some(_), _)) => break,
+                Either::Right((None, _)) => return,
+            }
+        }
+    }
+}
+
+fn vpn_events(
+    uuid: &UUID,
+    name: &str,
+    state: VpnState,
+    reason: Option<ActiveConnectionStateReason>,
+) -> Vec<VpnEvent> {
+    let mut events = vec![VpnEvent::StateChanged {
+        uuid: uuid.clone(),
+        name: name.to_owned(),
+        state,
+        reason,
+    }];
+
+    if state == VpnState::NeedAuth
+        || (state == VpnState::Failed && reason == Some(ActiveConnectionStateReason::NoSecrets))
+    {
+        events.push(VpnEvent::SecretsRequired {
+            uuid: uuid.clone(),
+            name: name.to_owned(),
+        });
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imported_uuid() {
+        assert_eq!(
+            imported_uuid(
+                "Connection 'office (backup)' (5c9f2e1a-8f43-4a6e-9d2b-3f0e4c7a1b2d) successfully added.\n"
+            ),
+            Some("5c9f2e1a-8f43-4a6e-9d2b-3f0e4c7a1b2d")
+        );
+        assert_eq!(imported_uuid("Connection 'office (backup)' added.\n"), None);
+        assert_eq!(imported_uuid(""), None);
+    }
+}
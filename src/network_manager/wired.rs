@@ -0,0 +1,61 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ActivationWatch, Error, eap::EnterpriseCredentials, find_connection, setting_value,
+    settings_for_update,
+};
+use cosmic_dbus_networkmanager::{interface::enums::DeviceType, nm::NetworkManager};
+
+/// Enables 802.1X authentication on a wired profile, or disables it when `enterprise` is `None`.
+///
+/// The profile is reactivated so the new credentials are tried right away, returning the reason
+/// if authentication fails. A profile which cannot be activated on any device is only saved.
+pub async fn set_8021x(
+    conn: &zbus::Connection,
+    uuid: &str,
+    enterprise: Option<&EnterpriseCredentials>,
+    password: Option<&str>,
+) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+
+    if setting_value::<String>(settings.get("connection"), "type").as_deref()
+        != Some("802-3-ethernet")
+    {
+        return Err(Error::NotWired);
+    }
+
+    let mut update = settings_for_update(&settings);
+    update.remove("802-1x");
+
+    if let Some(enterprise) = enterprise {
+        update.insert("802-1x", enterprise.settings(password));
+    }
+
+    connection.update(update).await?;
+
+    let nm = NetworkManager::new(conn).await?;
+
+    for device in nm.devices().await? {
+        if !matches!(device.device_type().await, Ok(DeviceType::Ethernet)) {
+            continue;
+        }
+
+        let mut available = false;
+        for available_connection in device.available_connections().await.unwrap_or_default() {
+            if available_connection.inner().path() == connection.inner().path() {
+                available = true;
+                break;
+            }
+        }
+
+        if available {
+            let watch = ActivationWatch::new(&device).await?;
+            let active_conn = nm.activate_connection(&connection, &device).await?;
+            return watch.wait(&active_conn).await;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,573 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    ConnectionSettings, Error, UUID, connection_settings, find_connection,
+    ip_network::IpNetwork,
+    profile::{IP_KEYS, IpConfig, IpMethod},
+    setting_value, settings_for_update,
+};
+use cosmic_dbus_networkmanager::settings::NetworkManagerSettings;
+use secure_string::SecureString;
+use std::{
+    collections::HashMap, fmt::Write as _, io::Write as _, net::IpAddr, path::Path, str::FromStr,
+};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+/// A WireGuard tunnel and its peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireGuardConfig {
+    /// Name of the connection profile.
+    pub name: String,
+    /// Name of the network interface created for the tunnel.
+    pub interface: String,
+    pub private_key: SecureString,
+    pub listen_port: Option<u16>,
+    pub fwmark: Option<u32>,
+    pub mtu: Option<u32>,
+    pub addresses: Vec<IpNetwork>,
+    pub dns: Vec<IpAddr>,
+    pub dns_search: Vec<String>,
+    pub peers: Vec<WireGuardPeer>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WireGuardPeer {
+    pub public_key: String,
+    /// `host:port` of the remote peer.
+    pub endpoint: Option<String>,
+    pub allowed_ips: Vec<IpNetwork>,
+    pub preshared_key: Option<SecureString>,
+    /// Keepalive interval in seconds.
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl WireGuardConfig {
+    /// Parses a `wg-quick` configuration, naming the profile and interface after `name`.
+    ///
+    /// Options that only `wg-quick` understands, such as `Table` or the `PostUp` hooks, are ignored.
+    pub fn from_wg_quick(name: &str, config: &str) -> Result<Self, Error> {
+        enum Section {
+            None,
+            Interface,
+            Peer,
+        }
+
+        let mut this = Self {
+            name: name.to_owned(),
+            interface: name.to_owned(),
+            private_key: SecureString::from(""),
+            listen_port: None,
+            fwmark: None,
+            mtu: None,
+            addresses: Vec::new(),
+            dns: Vec::new(),
+            dns_search: Vec::new(),
+            peers: Vec::new(),
+        };
+
+        let mut private_key = None;
+        let mut section = Section::None;
+
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                section = match line.to_ascii_lowercase().as_str() {
+                    "[interface]" => Section::Interface,
+                    "[peer]" => {
+                        this.peers.push(WireGuardPeer::default());
+                        Section::Peer
+                    }
+                    _ => return Err(invalid(number, "unknown section")),
+                };
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(number, "expected `Key = Value`"));
+            };
+
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+
+            match section {
+                Section::Interface => match key.as_str() {
+                    "privatekey" => private_key = Some(SecureString::from(value)),
+                    "listenport" => this.listen_port = Some(parse(number, value)?),
+                    "fwmark" => this.fwmark = parse_fwmark(number, value)?,
+                    "mtu" => this.mtu = Some(parse(number, value)?),
+                    "address" => {
+                        for address in list(value) {
+                            this.addresses.push(parse(number, address)?);
+                        }
+                    }
+                    // Entries which are not addresses are search domains.
+                    "dns" => {
+                        for entry in list(value) {
+                            match entry.parse() {
+                                Ok(address) => this.dns.push(address),
+                                Err(_) => this.dns_search.push(entry.to_owned()),
+                            }
+                        }
+                    }
+                    _ => tracing::debug!("ignoring unsupported WireGuard option {key}"),
+                },
+
+                Section::Peer => {
+                    let peer = this.peers.last_mut().unwrap();
+                    match key.as_str() {
+                        "publickey" => peer.public_key = value.to_owned(),
+                        "presharedkey" => peer.preshared_key = Some(SecureString::from(value)),
+                        "endpoint" => peer.endpoint = Some(value.to_owned()),
+                        "allowedips" => {
+                            for network in list(value) {
+                                peer.allowed_ips.push(parse(number, network)?);
+                            }
+                        }
+                        "persistentkeepalive" => {
+                            peer.persistent_keepalive = match value {
+                                "off" | "0" => None,
+                                value => Some(parse(number, value)?),
+                            }
+                        }
+                        _ => tracing::debug!("ignoring unsupported WireGuard peer option {key}"),
+                    }
+                }
+
+                Section::None => return Err(invalid(number, "option outside of a section")),
+            }
+        }
+
+        let Some(private_key) = private_key else {
+            return Err(Error::WireGuardConfig("missing PrivateKey".to_owned()));
+        };
+
+        if this.peers.iter().any(|peer| peer.public_key.is_empty()) {
+            return Err(Error::WireGuardConfig(
+                "peer without a PublicKey".to_owned(),
+            ));
+        }
+
+        this.private_key = private_key;
+        Ok(this)
+    }
+
+    /// Serializes the tunnel in the `wg-quick` configuration format.
+    pub fn to_wg_quick(&self) -> SecureString {
+        let mut config = String::from("[Interface]\n");
+
+        _ = writeln!(config, "PrivateKey = {}", self.private_key.unsecure());
+
+        if !self.addresses.is_empty() {
+            _ = writeln!(
+                config,
+                "Address = {}",
+                itertools::join(&self.addresses, ", ")
+            );
+        }
+
+        if !self.dns.is_empty() || !self.dns_search.is_empty() {
+            let dns = itertools::join(
+                self.dns
+                    .iter()
+                    .map(ToString::to_string)
+                    .chain(self.dns_search.iter().cloned()),
+                ", ",
+            );
+            _ = writeln!(config, "DNS = {dns}");
+        }
+
+        if let Some(port) = self.listen_port {
+            _ = writeln!(config, "ListenPort = {port}");
+        }
+
+        if let Some(fwmark) = self.fwmark {
+            _ = writeln!(config, "FwMark = {fwmark:#x}");
+        }
+
+        if let Some(mtu) = self.mtu {
+            _ = writeln!(config, "MTU = {mtu}");
+        }
+
+        for peer in &self.peers {
+            _ = writeln!(config, "\n[Peer]\nPublicKey = {}", peer.public_key);
+
+            if let Some(preshared_key) = peer.preshared_key.as_ref() {
+                _ = writeln!(config, "PresharedKey = {}", preshared_key.unsecure());
+            }
+
+            if !peer.allowed_ips.is_empty() {
+                _ = writeln!(
+                    config,
+                    "AllowedIPs = {}",
+                    itertools::join(&peer.allowed_ips, ", ")
+                );
+            }
+
+            if let Some(endpoint) = peer.endpoint.as_deref() {
+                _ = writeln!(config, "Endpoint = {endpoint}");
+            }
+
+            if let Some(keepalive) = peer.persistent_keepalive {
+                _ = writeln!(config, "PersistentKeepalive = {keepalive}");
+            }
+        }
+
+        SecureString::from(config)
+    }
+
+    /// Builds the settings of a `wireguard` connection profile.
+    pub fn settings(&self) -> HashMap<&'static str, HashMap<&'static str, Value<'_>>> {
+        let peers: Vec<HashMap<&str, Value>> =
+            self.peers.iter().map(WireGuardPeer::settings).collect();
+        let (ipv4, ipv6) = (self.ip_config(false), self.ip_config(true));
+
+        let mut wireguard = HashMap::from([
+            ("private-key", Value::from(self.private_key.unsecure())),
+            ("peers", Value::from(peers)),
+        ]);
+
+        if let Some(port) = self.listen_port {
+            wireguard.insert("listen-port", Value::U32(port.into()));
+        }
+
+        if let Some(fwmark) = self.fwmark {
+            wireguard.insert("fwmark", Value::U32(fwmark));
+        }
+
+        if let Some(mtu) = self.mtu {
+            wireguard.insert("mtu", Value::U32(mtu));
+        }
+
+        HashMap::from([
+            (
+                "connection",
+                HashMap::from([
+                    ("id", Value::from(self.name.as_str())),
+                    ("type", Value::from("wireguard")),
+                    ("interface-name", Value::from(self.interface.as_str())),
+                ]),
+            ),
+            ("wireguard", wireguard),
+            ("ipv4", ipv4.settings(false)),
+            ("ipv6", ipv6.settings(true)),
+        ])
+    }
+
+    fn ip_config(&self, ipv6: bool) -> IpConfig {
+        let addresses: Vec<IpNetwork> = self
+            .addresses
+            .iter()
+            .filter(|network| network.address.is_ipv6() == ipv6)
+            .copied()
+            .collect();
+
+        if addresses.is_empty() {
+            return IpConfig {
+                method: IpMethod::Disabled,
+                ..IpConfig::default()
+            };
+        }
+
+        IpConfig {
+            method: IpMethod::Manual,
+            addresses,
+            dns: self.dns.clone(),
+            dns_search: self.dns_search.clone(),
+            ..IpConfig::default()
+        }
+    }
+
+    /// Reads a profile from its connection settings, and the secrets of its `wireguard` setting.
+    pub fn from_settings(
+        settings: &ConnectionSettings,
+        secrets: &ConnectionSettings,
+    ) -> Result<Self, Error> {
+        let connection = settings.get("connection");
+        let wireguard = settings.get("wireguard");
+        let wireguard_secrets = secrets.get("wireguard");
+
+        if setting_value::<String>(connection, "type").as_deref() != Some("wireguard") {
+            return Err(Error::WireGuardConfig("not a WireGuard profile".to_owned()));
+        }
+
+        let secret_peers: Vec<HashMap<String, OwnedValue>> =
+            setting_value(wireguard_secrets, "peers").unwrap_or_default();
+
+        let peers = setting_value::<Vec<HashMap<String, OwnedValue>>>(wireguard, "peers")
+            .unwrap_or_default()
+            .iter()
+            .map(|peer| {
+                let public_key: String =
+                    setting_value(Some(peer), "public-key").unwrap_or_default();
+
+                let preshared_key = secret_peers
+                    .iter()
+                    .find(|secret| {
+                        setting_value::<String>(Some(secret), "public-key").as_ref()
+                            == Some(&public_key)
+                    })
+                    .and_then(|secret| setting_value::<String>(Some(secret), "preshared-key"))
+                    .filter(|key| !key.is_empty())
+                    .map(SecureString::from);
+
+                WireGuardPeer {
+                    endpoint: setting_value::<String>(Some(peer), "endpoint")
+                        .filter(|e| !e.is_empty()),
+                    allowed_ips: setting_value::<Vec<String>>(Some(peer), "allowed-ips")
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|network| network.parse().ok())
+                        .collect(),
+                    persistent_keepalive: setting_value::<u32>(Some(peer), "persistent-keepalive")
+                        .filter(|&keepalive| keepalive != 0)
+                        .and_then(|keepalive| u16::try_from(keepalive).ok()),
+                    preshared_key,
+                    public_key,
+                }
+            })
+            .collect();
+
+        let mut this = Self {
+            name: setting_value(connection, "id").unwrap_or_default(),
+            interface: setting_value(connection, "interface-name").unwrap_or_default(),
+            private_key: setting_value::<String>(wireguard_secrets, "private-key")
+                .or_else(|| setting_value(wireguard, "private-key"))
+                .map(SecureString::from)
+                .ok_or_else(|| Error::WireGuardConfig("missing private key".to_owned()))?,
+            listen_port: setting_value::<u32>(wireguard, "listen-port")
+                .filter(|&port| port != 0)
+                .and_then(|port| u16::try_from(port).ok()),
+            fwmark: setting_value::<u32>(wireguard, "fwmark").filter(|&fwmark| fwmark != 0),
+            mtu: setting_value::<u32>(wireguard, "mtu").filter(|&mtu| mtu != 0),
+            addresses: Vec::new(),
+            dns: Vec::new(),
+            dns_search: Vec::new(),
+            peers,
+        };
+
+        for (family, ipv6) in [("ipv4", false), ("ipv6", true)] {
+            let ip = IpConfig::from_setting(settings.get(family), ipv6);
+            this.addresses.extend(ip.addresses);
+            this.dns.extend(ip.dns);
+
+            for search in ip.dns_search {
+                if !this.dns_search.contains(&search) {
+                    this.dns_search.push(search);
+                }
+            }
+        }
+
+        Ok(this)
+    }
+}
+
+impl WireGuardPeer {
+    fn settings(&self) -> HashMap<&'static str, Value<'_>> {
+        let allowed_ips: Vec<String> = self.allowed_ips.iter().map(ToString::to_string).collect();
+
+        let mut settings = HashMap::from([
+            ("public-key", Value::from(self.public_key.as_str())),
+            ("allowed-ips", Value::from(allowed_ips)),
+        ]);
+
+        if let Some(endpoint) = self.endpoint.as_deref() {
+            settings.insert("endpoint", Value::from(endpoint));
+        }
+
+        if let Some(preshared_key) = self.preshared_key.as_ref() {
+            settings.insert("preshared-key", Value::from(preshared_key.unsecure()));
+            // Store the key with the profile rather than asking a secret agent for it.
+            settings.insert("preshared-key-flags", Value::U32(0));
+        }
+
+        if let Some(keepalive) = self.persistent_keepalive {
+            settings.insert("persistent-keepalive", Value::U32(keepalive.into()));
+        }
+
+        settings
+    }
+}
+
+/// Adds a new WireGuard connection profile, returning its UUID.
+pub async fn create(conn: &zbus::Connection, config: &WireGuardConfig) -> Result<UUID, Error> {
+    let nm_settings = NetworkManagerSettings::new(conn).await?;
+
+    let path: OwnedObjectPath = nm_settings
+        .inner()
+        .call("AddConnection", &(config.settings(),))
+        .await?;
+
+    // NetworkManager generates the UUID when normalizing the new profile.
+    let settings = connection_settings(conn, path).await?;
+
+    setting_value::<String>(settings.get("connection"), "uuid")
+        .map(UUID::from)
+        .ok_or(Error::ConnectionNotFound)
+}
+
+/// Replaces the tunnel, peers and addressing of an existing WireGuard profile.
+pub async fn update(
+    conn: &zbus::Connection,
+    uuid: &str,
+    config: &WireGuardConfig,
+) -> Result<(), Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let mut update = settings_for_update(&settings);
+
+    for (name, setting) in config.settings() {
+        let existing = update.entry(name).or_default();
+
+        match name {
+            "wireguard" => existing.clear(),
+            "ipv4" | "ipv6" => existing.retain(|key, _| !IP_KEYS.contains(key)),
+            _ => (),
+        }
+
+        existing.extend(setting);
+    }
+
+    connection.update(update).await?;
+
+    Ok(())
+}
+
+/// Reads a WireGuard profile, including its private and preshared keys.
+pub async fn load(conn: &zbus::Connection, uuid: &str) -> Result<WireGuardConfig, Error> {
+    let connection = find_connection(conn, uuid).await?;
+    let settings = connection.get_settings().await?;
+    let secrets: ConnectionSettings = connection
+        .inner()
+        .call("GetSecrets", &("wireguard",))
+        .await?;
+
+    WireGuardConfig::from_settings(&settings, &secrets)
+}
+
+/// Writes a WireGuard profile to `path` as a `wg-quick` configuration readable only by its owner.
+///
+/// The configuration is written to a new file which replaces `path`, so that an existing file
+/// cannot lend it wider permissions.
+pub async fn export(conn: &zbus::Connection, uuid: &str, path: &Path) -> Result<(), Error> {
+    let config = load(conn, uuid).await?.to_wg_quick();
+    let path = path.to_owned();
+
+    tokio::task::spawn_blocking(move || write_private(&path, config.unsecure().as_bytes()))
+        .await
+        .map_err(|why| Error::WireGuardExport(std::io::Error::other(why)))?
+        .map_err(Error::WireGuardExport)
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    // A file left behind by an interrupted export is of no use.
+    _ = std::fs::remove_file(&temp_path);
+
+    let written = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp_path, path));
+
+    if written.is_err() {
+        _ = std::fs::remove_file(&temp_path);
+    }
+
+    written
+}
+
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+fn parse<T: FromStr>(line: usize, value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| invalid(line, &format!("invalid value `{value}`")))
+}
+
+fn parse_fwmark(line: usize, value: &str) -> Result<Option<u32>, Error> {
+    if value == "off" {
+        return Ok(None);
+    }
+
+    let fwmark = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+
+    fwmark
+        .map(|fwmark| (fwmark != 0).then_some(fwmark))
+        .ok_or_else(|| invalid(line, &format!("invalid FwMark `{value}`")))
+}
+
+fn invalid(line: usize, why: &str) -> Error {
+    Error::WireGuardConfig(format!("line {}: {why}", line + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+[Interface]
+PrivateKey = yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=
+Address = 10.0.0.2/24, fd00::2/64
+DNS = 10.0.0.1, corp.example
+ListenPort = 51820
+PostUp = iptables -A FORWARD -i %i -j ACCEPT # ignored
+
+[Peer]
+PublicKey = xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=
+PresharedKey = /UwcSPg38hW/D9Y3tcS1FOV0K1wuURMbS0sesJEP5ak=
+AllowedIPs = 0.0.0.0/0, ::/0
+Endpoint = vpn.example.com:51820
+PersistentKeepalive = 25
+";
+
+    #[test]
+    fn test_wg_quick_round_trip() {
+        let config = WireGuardConfig::from_wg_quick("wg0", CONFIG).unwrap();
+
+        assert_eq!(config.listen_port, Some(51820));
+        assert_eq!(config.addresses.len(), 2);
+        assert_eq!(config.dns, vec![IpAddr::from([10, 0, 0, 1])]);
+        assert_eq!(config.dns_search, vec!["corp.example".to_owned()]);
+        assert_eq!(config.peers.len(), 1);
+        assert_eq!(config.peers[0].persistent_keepalive, Some(25));
+        assert!(config.peers[0].public_key.ends_with("8Dg="));
+
+        let serialized = config.to_wg_quick();
+        let reparsed = WireGuardConfig::from_wg_quick("wg0", serialized.unsecure()).unwrap();
+        assert_eq!(config, reparsed);
+    }
+
+    #[test]
+    fn test_wg_quick_errors() {
+        assert!(WireGuardConfig::from_wg_quick("wg0", "[Peer]\nPublicKey = abc").is_err());
+        assert!(WireGuardConfig::from_wg_quick("wg0", "PrivateKey = abc").is_err());
+        assert!(
+            WireGuardConfig::from_wg_quick("wg0", "[Interface]\nPrivateKey = a\nMTU = big")
+                .is_err()
+        );
+    }
+}
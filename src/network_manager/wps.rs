@@ -0,0 +1,194 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{Error, Event, SSID, active_connection_from_path, hw_address::HwAddress};
+use cosmic_dbus_networkmanager::{
+    device::SpecificDevice, interface::enums::DeviceState, nm::NetworkManager,
+};
+use futures::{SinkExt, StreamExt};
+use std::{collections::HashMap, time::Duration};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+
+/// `NM_SETTING_WIRELESS_SECURITY_WPS_METHOD_PBC`
+const WPS_METHOD_PBC: u32 = 0x4;
+
+/// How long an access point accepts a push-button session after its button is pressed.
+const WALK_TIME: Duration = Duration::from_secs(120);
+
+/// Progress of a push-button WPS connection started with [`super::Request::ConnectWps`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WpsProgress {
+    /// Press the WPS button on the access point.
+    WaitingForButton,
+    /// The access point handed over its credentials and the connection is being set up.
+    Negotiating,
+    Done,
+    /// The button was not pressed within the walk time.
+    TimedOut,
+    Failed,
+}
+
+impl WpsProgress {
+    /// Maps a device state change to the progress it represents, if it differs from `current`.
+    fn from_device_state(state: DeviceState, current: Self) -> Option<Self> {
+        let progress = match state {
+            // The supplicant waits for the button while NetworkManager waits for secrets.
+            DeviceState::NeedAuth => Self::WaitingForButton,
+            DeviceState::IpConfig | DeviceState::IpCheck | DeviceState::Secondaries => {
+                Self::Negotiating
+            }
+            DeviceState::Activated => Self::Done,
+            DeviceState::Failed => Self::Failed,
+            _ => return None,
+        };
+
+        (progress != current).then_some(progress)
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, Self::Done | Self::TimedOut | Self::Failed)
+    }
+}
+
+/// Joins an access point by WPS push-button, reporting progress as [`Event::Wps`].
+///
+/// The profile created for the attempt is removed again if it does not succeed.
+pub async fn connect(
+    conn: &zbus::Connection,
+    ssid: &SSID,
+    hw_address: HwAddress,
+    ap_path: &ObjectPath<'_>,
+    output: &mut futures::channel::mpsc::Sender<Event>,
+) -> Result<(), Error> {
+    let nm = NetworkManager::new(conn).await?;
+
+    let mut wifi_device = None;
+    for device in nm.devices().await? {
+        if let Ok(Some(SpecificDevice::Wireless(wireless))) = device.downcast_to_device().await {
+            let address = wireless.hw_address().await.unwrap_or_default();
+            if HwAddress::from_str(&address) == Some(hw_address) {
+                wifi_device = Some(device);
+                break;
+            }
+        }
+    }
+
+    let Some(device) = wifi_device else {
+        return Err(Error::NoWiFiDevices);
+    };
+
+    let mut states = device
+        .inner()
+        .receive_property_changed::<u32>("State")
+        .await;
+
+    let (profile, active_conn) = nm
+        .add_and_activate_connection(settings(ssid), device.inner().path(), ap_path)
+        .await?;
+    let active_conn = active_connection_from_path(conn, active_conn).await?;
+
+    _ = output.send(Event::Wps(WpsProgress::WaitingForButton)).await;
+
+    let deadline = tokio::time::Instant::now() + WALK_TIME;
+    let mut progress = WpsProgress::WaitingForButton;
+    let mut activating = false;
+    while !progress.is_finished() {
+        let next = match tokio::time::timeout_at(deadline, states.next()).await {
+            Ok(Some(change)) => {
+                let state = DeviceState::from(change.get().await.unwrap_or_default());
+
+                // The first state received may still describe the previous connection.
+                activating |= matches!(
+                    state,
+                    DeviceState::Prepare | DeviceState::Config | DeviceState::NeedAuth
+                );
+
+                if activating {
+                    WpsProgress::from_device_state(state, progress)
+                } else {
+                    None
+                }
+            }
+            Ok(None) => Some(WpsProgress::Failed),
+            Err(_) => Some(WpsProgress::TimedOut),
+        };
+
+        if let Some(next) = next {
+            progress = next;
+            _ = output.send(Event::Wps(progress)).await;
+        }
+    }
+
+    if progress == WpsProgress::Done {
+        return Ok(());
+    }
+
+    _ = nm.deactivate_connection(&active_conn).await;
+    _ = delete_profile(conn, profile).await;
+
+    Err(Error::ConnectionActivate)
+}
+
+async fn delete_profile(conn: &zbus::Connection, path: OwnedObjectPath) -> zbus::Result<()> {
+    let connection = zbus::Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        path,
+        "org.freedesktop.NetworkManager.Settings.Connection",
+    )
+    .await?;
+
+    connection.call("Delete", &()).await
+}
+
+/// A WPA-PSK profile whose passphrase NetworkManager obtains over WPS.
+fn settings(ssid: &SSID) -> HashMap<&'static str, HashMap<&'static str, Value<'_>>> {
+    HashMap::from([
+        (
+            "connection",
+            HashMap::from([
+                ("id", Value::from(ssid.to_string())),
+                ("type", Value::from("802-11-wireless")),
+            ]),
+        ),
+        (
+            "802-11-wireless",
+            HashMap::from([
+                ("ssid", Value::Array(ssid.as_bytes().into())),
+                ("mode", Value::from("infrastructure")),
+                ("security", Value::from("802-11-wireless-security")),
+            ]),
+        ),
+        (
+            "802-11-wireless-security",
+            HashMap::from([
+                ("key-mgmt", Value::from("wpa-psk")),
+                ("wps-method", Value::U32(WPS_METHOD_PBC)),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_from_device_state() {
+        use WpsProgress::*;
+        let progress = WpsProgress::from_device_state;
+
+        assert_eq!(progress(DeviceState::Config, WaitingForButton), None);
+        assert_eq!(progress(DeviceState::NeedAuth, WaitingForButton), None);
+        assert_eq!(
+            progress(DeviceState::IpConfig, WaitingForButton),
+            Some(Negotiating)
+        );
+        assert_eq!(progress(DeviceState::IpCheck, Negotiating), None);
+        assert_eq!(progress(DeviceState::Activated, Negotiating), Some(Done));
+        assert_eq!(
+            progress(DeviceState::Failed, WaitingForButton),
+            Some(Failed)
+        );
+    }
+}